@@ -16,7 +16,7 @@ fn main() {
     let window = winit::window::Window::new(&event_loop).unwrap();
     wgpu_subscriber::initialize_default_subscriber(None);
     let mut renderer = pollster::block_on(Renderer::new(&window));
-    stretch::stretch(1600.0, 900.0);
+    stretch::stretch(1600.0, 900.0, renderer.fonts());
 
     /*let mut polygon = Polygon::new(
         &mut renderer,
@@ -46,13 +46,13 @@ fn main() {
     lines.set_line_color(0, Color::RED);
     //lines.set_line_position(1, Point { x: 1.0, y: 0.0 }, Point { x: 0.0, y: 1.0 });
     lines.set_line_position(1, Point2::new(1.0, 0.5), Point2::new(0.5, 1.0));
-    lines.set_line_color(1, Color::GREEN);
+    lines.set_line_color(1, Color::LIME);
     //lines.set_line_position(2, Point { x: 0.0, y: 1.0 }, Point { x: -1.0, y: 0.0 });
     lines.set_line_position(2, Point2::new(0.5, 1.0), Point2::new(0.0, 0.5));
     lines.set_line_color(2, Color::BLUE);
     //lines.set_line_position(3, Point { x: -1.0, y: 0.0 }, Point { x: 0.0, y: -1.0 });
     lines.set_line_position(3, Point2::new(0.0, 0.5), Point2::new(0.5, 0.0));
-    lines.set_line_color(3, Color::GREEN);
+    lines.set_line_color(3, Color::LIME);
     //lines.set_position(Point { x: 0.3, y: 0.3 });
     lines.draw(&mut renderer, Point2::new(0.0, 0.0));
 
@@ -171,7 +171,7 @@ fn main() {
                 //timing = Instant::now();
                 renderer.render_start();
                 renderer.draw_text("test1", Point2::new(50.0, 50.0), Color::RED, 32.0);
-                renderer.draw_text("test2", Point2::new(50.0, 70.0), Color::GREEN, 20.0);
+                renderer.draw_text("test2", Point2::new(50.0, 70.0), Color::LIME, 20.0);
                 renderer.render_finish();*/
 
                 //window.request_redraw();
@@ -194,7 +194,7 @@ fn main() {
                 }*/
                 renderer.render_start();
                 renderer.draw_text("test1", Point2::new(50.0, 50.0), Color::RED, 32.0);
-                renderer.draw_text("test2", Point2::new(50.0, 70.0), Color::GREEN, 20.0);
+                renderer.draw_text("test2", Point2::new(50.0, 70.0), Color::LIME, 20.0);
                 renderer.render_finish();
                 let elapsed = start.elapsed();
                 println!("renderer render {:?}", elapsed);
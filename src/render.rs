@@ -1,11 +1,24 @@
+mod background;
+pub use background::{Background, BackgroundError};
 mod color;
 pub use color::Color;
 mod dual_buffer;
-pub use dual_buffer::DualBuffer;
+pub use dual_buffer::{AllocationHandle, DualBuffer, DualBufferError};
+mod fonts;
+pub use fonts::{FontError, FontRegistry};
+pub use wgpu_glyph::FontId;
 mod point;
 pub use point::{Point2D, Point3D};
 mod renderer;
-pub use renderer::Renderer;
+pub use renderer::{Antialiasing, FrameStats, Renderer, RendererConfig};
+mod text_sections;
+pub use text_sections::{SectionHandle, TextSection, TextSections};
+mod texture_atlas;
+pub use texture_atlas::{
+    extrude, AtlasConfig, AtlasEntry, TextureAtlas, TextureId, TextureRect, UvRect,
+};
+mod textures;
+pub use textures::{Filter, TextureError};
 
 pub type Point = Point2D;
 pub type Point2 = cgmath::Point2<f32>;
@@ -18,6 +31,13 @@ pub type Vector3 = cgmath::Vector3<f32>;
 pub struct Vertex {
     pub position: Point3D,
     pub color: Color,
+    /// Where to sample the texture atlas, which multiplies the colour. The
+    /// default samples a white pixel.
+    pub tex_coords: [f32; 2],
+    /// How the texture atlas is sampled, see [`Filter::to_vertex`].
+    pub filter: u32,
+    /// Texture atlas page that `tex_coords` are on.
+    pub page: u32,
 }
 
 impl Vertex {
@@ -34,7 +54,30 @@ impl Vertex {
                 wgpu::VertexAttributeDescriptor {
                     offset: std::mem::size_of::<Point3D>() as wgpu::BufferAddress,
                     shader_location: 1,
-                    format: wgpu::VertexFormat::Float3,
+                    format: wgpu::VertexFormat::Float4,
+                },
+                wgpu::VertexAttributeDescriptor {
+                    offset: (std::mem::size_of::<Point3D>() + std::mem::size_of::<Color>())
+                        as wgpu::BufferAddress,
+                    shader_location: 2,
+                    format: wgpu::VertexFormat::Float2,
+                },
+                wgpu::VertexAttributeDescriptor {
+                    offset: (std::mem::size_of::<Point3D>()
+                        + std::mem::size_of::<Color>()
+                        + std::mem::size_of::<[f32; 2]>())
+                        as wgpu::BufferAddress,
+                    shader_location: 3,
+                    format: wgpu::VertexFormat::Uint,
+                },
+                wgpu::VertexAttributeDescriptor {
+                    offset: (std::mem::size_of::<Point3D>()
+                        + std::mem::size_of::<Color>()
+                        + std::mem::size_of::<[f32; 2]>()
+                        + std::mem::size_of::<u32>())
+                        as wgpu::BufferAddress,
+                    shader_location: 4,
+                    format: wgpu::VertexFormat::Uint,
                 },
             ],
         }
@@ -0,0 +1,131 @@
+use crate::{Color, Point2, Point3D, Vertex};
+use std::fmt;
+
+/// Number of triangles in the disc of a radial gradient.
+const RADIAL_SEGMENTS: u32 = 64;
+
+/// What the renderer draws before any shapes.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Background {
+    /// Clear to a single colour.
+    Solid(Color),
+    /// Full-screen gradient from the top edge to the bottom edge.
+    VerticalGradient { top: Color, bottom: Color },
+    /// Gradient from `inner` at `center` to `outer` at `radius` and beyond.
+    ///
+    /// Both are in normalised coordinates, so the gradient stretches with the
+    /// window.
+    RadialGradient {
+        center: Point2,
+        radius: f32,
+        inner: Color,
+        outer: Color,
+    },
+    /// Keep what was drawn into the frame with
+    /// [`Renderer::encode_underneath`](crate::Renderer::encode_underneath)
+    /// and draw the shapes over it. Anything else is undefined, as window
+    /// frames don't keep their contents once presented.
+    ///
+    /// Not available with multisampling, as the resolved shapes would
+    /// replace the frame.
+    NoClear,
+}
+
+/// Errors returned when setting a background.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BackgroundError {
+    /// [`Background::NoClear`] was set on a multisampled renderer.
+    NoClearWithMultisampling,
+}
+
+impl fmt::Display for BackgroundError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            BackgroundError::NoClearWithMultisampling => {
+                write!(f, "no-clear backgrounds can't be used with multisampling")
+            }
+        }
+    }
+}
+
+impl std::error::Error for BackgroundError {}
+
+impl Default for Background {
+    fn default() -> Self {
+        Background::Solid(Color::linear(0.1, 0.2, 0.3))
+    }
+}
+
+impl Background {
+    /// Colour the target is cleared to, or `None` to keep its contents.
+    pub(crate) fn clear_color(&self) -> Option<wgpu::Color> {
+        let color = match *self {
+            Background::Solid(color) => color,
+            Background::VerticalGradient { top, .. } => top,
+            Background::RadialGradient { outer, .. } => outer,
+            Background::NoClear => return None,
+        };
+
+        Some(wgpu::Color {
+            r: (color.r * color.a) as f64,
+            g: (color.g * color.a) as f64,
+            b: (color.b * color.a) as f64,
+            a: color.a as f64,
+        })
+    }
+
+    /// Vertices and indices drawn over the clear colour, if any.
+    pub(crate) fn mesh(&self) -> Option<(Vec<Vertex>, Vec<u32>)> {
+        match *self {
+            Background::VerticalGradient { top, bottom } => {
+                let corner = |x, y, color| Vertex {
+                    position: Point3D::from(Point2::new(x, y)),
+                    color,
+                    ..Vertex::default()
+                };
+                let vertices = vec![
+                    corner(0.0, 0.0, top),
+                    corner(0.0, 1.0, bottom),
+                    corner(1.0, 1.0, bottom),
+                    corner(1.0, 0.0, top),
+                ];
+
+                Some((vertices, vec![0, 1, 2, 0, 2, 3]))
+            }
+            Background::RadialGradient {
+                center,
+                radius,
+                inner,
+                outer,
+            } => {
+                let mut vertices = Vec::with_capacity(RADIAL_SEGMENTS as usize + 1);
+                vertices.push(Vertex {
+                    position: Point3D::from(center),
+                    color: inner,
+                    ..Vertex::default()
+                });
+                let step = std::f32::consts::PI * 2.0 / RADIAL_SEGMENTS as f32;
+                for i in 0..RADIAL_SEGMENTS {
+                    let angle = i as f32 * step;
+                    let point = Point2::new(
+                        center.x + radius * angle.cos(),
+                        center.y + radius * angle.sin(),
+                    );
+                    vertices.push(Vertex {
+                        position: Point3D::from(point),
+                        color: outer,
+                        ..Vertex::default()
+                    });
+                }
+
+                let mut indices = Vec::with_capacity(RADIAL_SEGMENTS as usize * 3);
+                for i in 0..RADIAL_SEGMENTS {
+                    indices.extend_from_slice(&[0, i + 1, (i + 1) % RADIAL_SEGMENTS + 1]);
+                }
+
+                Some((vertices, indices))
+            }
+            Background::Solid(_) | Background::NoClear => None,
+        }
+    }
+}
@@ -1,51 +1,410 @@
+mod named;
+use named::NAMED_COLORS;
+
+/// A colour stored as linear RGB with straight (non-premultiplied) alpha.
+///
+/// Shaders premultiply the alpha before blending. Render targets are sRGB, so
+/// the GPU encodes the linear values on write. Constructors taking bytes, hex
+/// strings, names, HSL or HSV expect sRGB encoded values and decode them.
 #[repr(C)]
-#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable, PartialEq)]
 pub struct Color {
     pub r: f32,
     pub g: f32,
     pub b: f32,
+    pub a: f32,
+}
+
+/// Decode an sRGB encoded component to linear.
+fn srgb_to_linear(c: f32) -> f32 {
+    if c <= 0.04045 {
+        c / 12.92
+    } else {
+        ((c + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+/// Encode a linear component as sRGB.
+fn linear_to_srgb(c: f32) -> f32 {
+    if c <= 0.003_130_8 {
+        c * 12.92
+    } else {
+        1.055 * c.powf(1.0 / 2.4) - 0.055
+    }
+}
+
+/// Build sRGB components from a hue in degrees, chroma and the lightness offset.
+fn from_hue_chroma(hue: f32, chroma: f32, m: f32) -> (f32, f32, f32) {
+    let h = hue.rem_euclid(360.0) / 60.0;
+    let x = chroma * (1.0 - (h % 2.0 - 1.0).abs());
+    let (r, g, b) = match h as u32 {
+        0 => (chroma, x, 0.0),
+        1 => (x, chroma, 0.0),
+        2 => (0.0, chroma, x),
+        3 => (0.0, x, chroma),
+        4 => (x, 0.0, chroma),
+        _ => (chroma, 0.0, x),
+    };
+    (r + m, g + m, b + m)
+}
+
+/// Hue in degrees of sRGB components with the given maximum and chroma.
+fn hue(r: f32, g: f32, b: f32, max: f32, chroma: f32) -> f32 {
+    if chroma == 0.0 {
+        0.0
+    } else if max == r {
+        60.0 * ((g - b) / chroma).rem_euclid(6.0)
+    } else if max == g {
+        60.0 * ((b - r) / chroma + 2.0)
+    } else {
+        60.0 * ((r - g) / chroma + 4.0)
+    }
 }
 
 impl Color {
+    /// Linear RGB components in `0.0..=1.0`.
+    pub fn linear(r: f32, g: f32, b: f32) -> Self {
+        Self::linear_rgba(r, g, b, 1.0)
+    }
+
+    pub fn linear_rgba(r: f32, g: f32, b: f32, a: f32) -> Self {
+        Self { r, g, b, a }
+    }
+
+    /// sRGB encoded components in `0.0..=1.0`.
+    pub fn srgb(r: f32, g: f32, b: f32) -> Self {
+        Self::srgba(r, g, b, 1.0)
+    }
+
+    pub fn srgba(r: f32, g: f32, b: f32, a: f32) -> Self {
+        Self {
+            r: srgb_to_linear(r),
+            g: srgb_to_linear(g),
+            b: srgb_to_linear(b),
+            a,
+        }
+    }
+
+    /// sRGB encoded bytes.
     pub fn rgb(r: u8, g: u8, b: u8) -> Self {
+        Self::rgba(r, g, b, 255)
+    }
+
+    pub fn rgba(r: u8, g: u8, b: u8, a: u8) -> Self {
+        Self::srgba(
+            r as f32 / 255.0,
+            g as f32 / 255.0,
+            b as f32 / 255.0,
+            a as f32 / 255.0,
+        )
+    }
+
+    /// Parse `#RRGGBB` or `#RRGGBBAA`. The leading `#` is optional.
+    pub fn from_hex(hex: &str) -> Option<Self> {
+        let hex = hex.strip_prefix('#').unwrap_or(hex);
+        if !hex.is_ascii() || (hex.len() != 6 && hex.len() != 8) {
+            return None;
+        }
+        let channel = |i: usize| u8::from_str_radix(&hex[i..(i + 2)], 16).ok();
+        let a = if hex.len() == 8 { channel(6)? } else { 255 };
+
+        Some(Self::rgba(channel(0)?, channel(2)?, channel(4)?, a))
+    }
+
+    /// Look up a CSS colour name, ignoring case.
+    pub fn named(name: &str) -> Option<Self> {
+        let name = name.to_ascii_lowercase();
+        NAMED_COLORS
+            .binary_search_by(|(named, _)| named.cmp(&name.as_str()))
+            .ok()
+            .map(|i| {
+                let rgb = NAMED_COLORS[i].1;
+                Self::rgb((rgb >> 16) as u8, (rgb >> 8) as u8, rgb as u8)
+            })
+    }
+
+    /// Hue in degrees, saturation and lightness in `0.0..=1.0`.
+    pub fn from_hsl(hue: f32, saturation: f32, lightness: f32) -> Self {
+        let chroma = (1.0 - (2.0 * lightness - 1.0).abs()) * saturation;
+        let (r, g, b) = from_hue_chroma(hue, chroma, lightness - chroma / 2.0);
+        Self::srgb(r, g, b)
+    }
+
+    /// Hue in degrees, saturation and value in `0.0..=1.0`.
+    pub fn from_hsv(hue: f32, saturation: f32, value: f32) -> Self {
+        let chroma = value * saturation;
+        let (r, g, b) = from_hue_chroma(hue, chroma, value - chroma);
+        Self::srgb(r, g, b)
+    }
+
+    /// Colour from OKLab lightness and `a`/`b` components.
+    pub fn from_oklab(l: f32, a: f32, b: f32) -> Self {
+        let l_ = (l + 0.396_337_78 * a + 0.215_803_76 * b).powi(3);
+        let m_ = (l - 0.105_561_346 * a - 0.063_854_17 * b).powi(3);
+        let s_ = (l - 0.089_484_18 * a - 1.291_485_5 * b).powi(3);
+
+        Self::linear(
+            4.076_741_7 * l_ - 3.307_711_6 * m_ + 0.230_969_94 * s_,
+            -1.268_438 * l_ + 2.609_757_4 * m_ - 0.341_319_38 * s_,
+            -0.004_196_086_3 * l_ - 0.703_418_6 * m_ + 1.707_614_7 * s_,
+        )
+    }
+
+    /// The same colour with a different alpha.
+    pub fn with_alpha(self, a: f32) -> Self {
+        Self { a, ..self }
+    }
+
+    /// sRGB encoded components and alpha.
+    pub fn to_srgb(self) -> [f32; 4] {
+        [
+            linear_to_srgb(self.r),
+            linear_to_srgb(self.g),
+            linear_to_srgb(self.b),
+            self.a,
+        ]
+    }
+
+    /// sRGB encoded bytes and alpha.
+    pub fn to_rgba8(self) -> [u8; 4] {
+        let [r, g, b, a] = self.to_srgb();
+        let byte = |c: f32| (c.clamp(0.0, 1.0) * 255.0).round() as u8;
+        [byte(r), byte(g), byte(b), byte(a)]
+    }
+
+    /// `#RRGGBB`, or `#RRGGBBAA` if the colour isn't opaque.
+    pub fn to_hex(self) -> String {
+        let [r, g, b, a] = self.to_rgba8();
+        if a == 255 {
+            format!("#{:02X}{:02X}{:02X}", r, g, b)
+        } else {
+            format!("#{:02X}{:02X}{:02X}{:02X}", r, g, b, a)
+        }
+    }
+
+    /// Hue in degrees, saturation and lightness.
+    pub fn to_hsl(self) -> (f32, f32, f32) {
+        let [r, g, b, _] = self.to_srgb();
+        let max = r.max(g).max(b);
+        let min = r.min(g).min(b);
+        let chroma = max - min;
+        let lightness = (max + min) / 2.0;
+        let saturation = if chroma == 0.0 {
+            0.0
+        } else {
+            chroma / (1.0 - (2.0 * lightness - 1.0).abs())
+        };
+        (hue(r, g, b, max, chroma), saturation, lightness)
+    }
+
+    /// Hue in degrees, saturation and value.
+    pub fn to_hsv(self) -> (f32, f32, f32) {
+        let [r, g, b, _] = self.to_srgb();
+        let max = r.max(g).max(b);
+        let min = r.min(g).min(b);
+        let chroma = max - min;
+        let saturation = if max == 0.0 { 0.0 } else { chroma / max };
+        (hue(r, g, b, max, chroma), saturation, max)
+    }
+
+    /// OKLab lightness and `a`/`b` components.
+    pub fn to_oklab(self) -> (f32, f32, f32) {
+        let l = 0.412_221_46 * self.r + 0.536_332_55 * self.g + 0.051_445_995 * self.b;
+        let m = 0.211_903_5 * self.r + 0.680_699_5 * self.g + 0.107_396_96 * self.b;
+        let s = 0.088_302_46 * self.r + 0.281_718_85 * self.g + 0.629_978_7 * self.b;
+        let (l_, m_, s_) = (l.cbrt(), m.cbrt(), s.cbrt());
+
+        (
+            0.210_454_26 * l_ + 0.793_617_8 * m_ - 0.004_072_047 * s_,
+            1.977_998_5 * l_ - 2.428_592_2 * m_ + 0.450_593_7 * s_,
+            0.025_904_037 * l_ + 0.782_771_77 * m_ - 0.808_675_77 * s_,
+        )
+    }
+
+    /// Interpolate in linear RGB. `t` of `0.0` gives `self`, `1.0` gives `other`.
+    pub fn lerp(self, other: Self, t: f32) -> Self {
         Self {
-            r: r as f32 / 255.0,
-            g: g as f32 / 255.0,
-            b: b as f32 / 255.0,
+            r: self.r + (other.r - self.r) * t,
+            g: self.g + (other.g - self.g) * t,
+            b: self.b + (other.b - self.b) * t,
+            a: self.a + (other.a - self.a) * t,
         }
     }
 
+    /// Interpolate in OKLab, which keeps perceived lightness even.
+    pub fn lerp_oklab(self, other: Self, t: f32) -> Self {
+        let (l1, a1, b1) = self.to_oklab();
+        let (l2, a2, b2) = other.to_oklab();
+        Self::from_oklab(l1 + (l2 - l1) * t, a1 + (a2 - a1) * t, b1 + (b2 - b1) * t)
+            .with_alpha(self.a + (other.a - self.a) * t)
+    }
+
     pub const RED: Color = Color {
         r: 1.0,
         g: 0.0,
         b: 0.0,
+        a: 1.0,
     };
 
     pub const BLUE: Color = Color {
         r: 0.0,
         g: 0.0,
         b: 1.0,
+        a: 1.0,
     };
 
+    /// CSS `green`, `#008000`. Use [`Color::LIME`] for full intensity green.
     pub const GREEN: Color = Color {
+        r: 0.0,
+        g: 0.215_860_5,
+        b: 0.0,
+        a: 1.0,
+    };
+
+    pub const LIME: Color = Color {
         r: 0.0,
         g: 1.0,
         b: 0.0,
+        a: 1.0,
     };
 
     pub const BLACK: Color = Color {
         r: 0.0,
         g: 0.0,
         b: 0.0,
+        a: 1.0,
+    };
+
+    pub const WHITE: Color = Color {
+        r: 1.0,
+        g: 1.0,
+        b: 1.0,
+        a: 1.0,
+    };
+
+    pub const TRANSPARENT: Color = Color {
+        r: 0.0,
+        g: 0.0,
+        b: 0.0,
+        a: 0.0,
     };
 }
 
 impl Default for Color {
     fn default() -> Self {
-        Self {
-            r: 1.0,
-            g: 1.0,
-            b: 1.0,
+        Self::WHITE
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(a: f32, b: f32) {
+        assert!((a - b).abs() < 1e-3, "{} != {}", a, b);
+    }
+
+    fn assert_color_close(a: Color, b: Color) {
+        assert_close(a.r, b.r);
+        assert_close(a.g, b.g);
+        assert_close(a.b, b.b);
+        assert_close(a.a, b.a);
+    }
+
+    #[test]
+    fn bytes_are_decoded_from_srgb() {
+        let grey = Color::rgb(128, 128, 128);
+        assert_close(grey.r, 0.215_860_5);
+        assert_eq!(grey.to_rgba8(), [128, 128, 128, 255]);
+    }
+
+    #[test]
+    fn every_byte_round_trips_through_linear() {
+        for byte in 0..=255 {
+            let color = Color::rgba(byte, byte, byte, byte);
+            assert_eq!(color.to_rgba8(), [byte, byte, byte, byte]);
         }
     }
+
+    #[test]
+    fn hex_round_trip() {
+        assert_eq!(Color::from_hex("#1E90FF").unwrap().to_hex(), "#1E90FF");
+        assert_eq!(Color::from_hex("663399cc").unwrap().to_hex(), "#663399CC");
+        assert!(Color::from_hex("#12345").is_none());
+        assert!(Color::from_hex("#GGGGGG").is_none());
+    }
+
+    #[test]
+    fn named_colors_match_table() {
+        for (name, rgb) in NAMED_COLORS {
+            let hex = format!("#{:06X}", rgb);
+            assert_eq!(Color::named(name).unwrap().to_hex(), hex);
+        }
+        assert_eq!(Color::named("RebeccaPurple").unwrap().to_hex(), "#663399");
+        assert!(Color::named("notacolor").is_none());
+    }
+
+    #[test]
+    fn constants_match_names() {
+        assert_eq!(Color::named("red"), Some(Color::RED));
+        assert_eq!(Color::named("blue"), Some(Color::BLUE));
+        assert_eq!(Color::named("lime"), Some(Color::LIME));
+        assert_eq!(Color::named("black"), Some(Color::BLACK));
+        assert_eq!(Color::named("white"), Some(Color::WHITE));
+        assert_color_close(Color::named("green").unwrap(), Color::GREEN);
+    }
+
+    #[test]
+    fn hsl_round_trip() {
+        for (name, _) in NAMED_COLORS {
+            let color = Color::named(name).unwrap();
+            let (h, s, l) = color.to_hsl();
+            assert_color_close(Color::from_hsl(h, s, l), color);
+        }
+        let (h, s, l) = Color::named("dodgerblue").unwrap().to_hsl();
+        assert_close(h, 209.6);
+        assert_close(s, 1.0);
+        assert_close(l, 0.559);
+    }
+
+    #[test]
+    fn hsv_round_trip() {
+        for (name, _) in NAMED_COLORS {
+            let color = Color::named(name).unwrap();
+            let (h, s, v) = color.to_hsv();
+            assert_color_close(Color::from_hsv(h, s, v), color);
+        }
+        let (h, s, v) = Color::named("orange").unwrap().to_hsv();
+        assert_close(h, 38.824);
+        assert_close(s, 1.0);
+        assert_close(v, 1.0);
+    }
+
+    #[test]
+    fn oklab_round_trip() {
+        for (name, _) in NAMED_COLORS {
+            let color = Color::named(name).unwrap();
+            let (l, a, b) = color.to_oklab();
+            assert_color_close(Color::from_oklab(l, a, b), color);
+        }
+        let (l, a, b) = Color::WHITE.to_oklab();
+        assert_close(l, 1.0);
+        assert_close(a, 0.0);
+        assert_close(b, 0.0);
+    }
+
+    #[test]
+    fn lerp_endpoints_and_midpoint() {
+        let from = Color::RED;
+        let to = Color::BLUE.with_alpha(0.0);
+        assert_color_close(from.lerp(to, 0.0), from);
+        assert_color_close(from.lerp(to, 1.0), to);
+        assert_color_close(from.lerp(to, 0.5), Color::linear_rgba(0.5, 0.0, 0.5, 0.5));
+        assert_color_close(from.lerp_oklab(to, 0.0), from);
+        assert_color_close(from.lerp_oklab(to, 1.0), to);
+
+        // Black to white in OKLab lands on perceptual mid grey.
+        let (l, _, _) = Color::BLACK.lerp_oklab(Color::WHITE, 0.5).to_oklab();
+        assert_close(l, 0.5);
+    }
 }
@@ -1,60 +1,286 @@
 use crate::render::{Point3D, Vertex};
+use std::fmt;
+use std::ops::Range;
+use std::sync::atomic::{AtomicU32, Ordering};
+
+/// Source of unique ids so handles can't be used on the wrong buffer.
+static NEXT_BUFFER_ID: AtomicU32 = AtomicU32::new(0);
+
+/// Errors returned by [`DualBuffer`] operations.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DualBufferError {
+    /// The vertex buffer can't grow to fit the allocation.
+    OutOfVertices,
+    /// The index buffer can't grow to fit the allocation.
+    OutOfIndices,
+    /// The handle's allocation has been freed.
+    StaleHandle,
+    /// The handle was allocated from a different buffer.
+    WrongBuffer,
+    /// A write runs past the end of the allocation.
+    OutOfRange,
+}
+
+impl fmt::Display for DualBufferError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            DualBufferError::OutOfVertices => write!(f, "not enough vertex space for allocation"),
+            DualBufferError::OutOfIndices => write!(f, "not enough index space for allocation"),
+            DualBufferError::StaleHandle => write!(f, "allocation handle has been freed"),
+            DualBufferError::WrongBuffer => {
+                write!(f, "allocation handle belongs to a different buffer")
+            }
+            DualBufferError::OutOfRange => write!(f, "write runs past the end of the allocation"),
+        }
+    }
+}
+
+impl std::error::Error for DualBufferError {}
+
+/// Refers to an allocation inside a specific [`DualBuffer`].
+///
+/// The generation changes every time a slot is freed, so old handles to a
+/// reused slot are detected as stale.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct AllocationHandle {
+    buffer_id: u32,
+    slot: u32,
+    generation: u32,
+}
 
 /// Represents allocated blocks in a set of vertex and index buffers.
 struct Allocation {
-    vertex_offset: wgpu::BufferAddress,
+    vertex_offset: u32,
     num_vertices: u32,
-    index_offset: wgpu::BufferAddress,
+    index_offset: u32,
     num_indices: u32,
+    z_index: i32,
+}
+
+/// An allocation slot and the generation of its current occupant.
+#[derive(Default)]
+struct Slot {
+    generation: u32,
+    allocation: Option<Allocation>,
+}
+
+/// A run of unused elements inside one of the buffers.
+#[derive(Clone, Copy, Debug)]
+struct FreeBlock {
+    offset: u32,
+    len: u32,
+}
+
+/// Free blocks of a single buffer, kept sorted by offset.
+#[derive(Default)]
+struct FreeList {
+    blocks: Vec<FreeBlock>,
+}
+
+impl FreeList {
+    /// Find the first block that can hold `len` elements.
+    fn find(&self, len: u32) -> Option<usize> {
+        if len == 0 {
+            return None;
+        }
+        self.blocks.iter().position(|block| block.len >= len)
+    }
+
+    /// Take `len` elements from the front of the block at `position`.
+    fn take(&mut self, position: usize, len: u32) -> u32 {
+        let block = &mut self.blocks[position];
+        let offset = block.offset;
+        block.offset += len;
+        block.len -= len;
+        if block.len == 0 {
+            self.blocks.remove(position);
+        }
+        offset
+    }
+
+    /// Return a block to the list, merging it with its neighbours.
+    fn insert(&mut self, offset: u32, len: u32) {
+        if len == 0 {
+            return;
+        }
+        let position = self
+            .blocks
+            .iter()
+            .position(|block| block.offset > offset)
+            .unwrap_or(self.blocks.len());
+        self.blocks.insert(position, FreeBlock { offset, len });
+
+        // Merge with the following block.
+        if position + 1 < self.blocks.len()
+            && self.blocks[position].offset + self.blocks[position].len
+                == self.blocks[position + 1].offset
+        {
+            self.blocks[position].len += self.blocks[position + 1].len;
+            self.blocks.remove(position + 1);
+        }
+
+        // Merge with the preceding block.
+        if position > 0
+            && self.blocks[position - 1].offset + self.blocks[position - 1].len
+                == self.blocks[position].offset
+        {
+            self.blocks[position - 1].len += self.blocks[position].len;
+            self.blocks.remove(position);
+        }
+    }
+
+    /// Remove a trailing block ending at `end` and return the new end.
+    fn trim(&mut self, end: u32) -> u32 {
+        match self.blocks.last() {
+            Some(block) if block.offset + block.len == end => {
+                let offset = block.offset;
+                self.blocks.pop();
+                offset
+            }
+            _ => end,
+        }
+    }
+
+    fn clear(&mut self) {
+        self.blocks.clear();
+    }
+}
+
+/// Element ranges of a buffer that changed since the last upload.
+#[derive(Default, Debug)]
+struct DirtyRanges {
+    ranges: Vec<Range<u32>>,
+}
+
+impl DirtyRanges {
+    fn mark(&mut self, range: Range<u32>) {
+        if range.start < range.end {
+            self.ranges.push(range);
+        }
+    }
+
+    /// Sort and merge overlapping or adjacent ranges, clipping them to `len`.
+    ///
+    /// The tracked ranges are cleared.
+    fn take_merged(&mut self, len: u32) -> Vec<Range<u32>> {
+        self.ranges.sort_by_key(|range| range.start);
+        let mut merged: Vec<Range<u32>> = Vec::new();
+        for range in self.ranges.drain(..) {
+            let range = range.start..range.end.min(len);
+            if range.start >= range.end {
+                continue;
+            }
+            match merged.last_mut() {
+                Some(last) if range.start <= last.end => last.end = last.end.max(range.end),
+                _ => merged.push(range),
+            }
+        }
+        merged
+    }
+}
+
+/// Convert a range of elements to a range of bytes.
+fn byte_range(range: &Range<u32>, element_size: u64) -> Range<u64> {
+    (range.start as u64 * element_size)..(range.end as u64 * element_size)
 }
 
 /// Holds and controls access to a set of vertex and index buffers.
+///
+/// The buffers grow when an allocation doesn't fit. Growth happens on the CPU
+/// side in [`DualBuffer::alloc`] and the GPU buffers are created or recreated
+/// on the next [`DualBuffer::write_buffer`].
 pub struct DualBuffer {
-    pub vertex_buffer: wgpu::Buffer,
+    id: u32,
+    label: String,
+    vertex_buffer: Option<wgpu::Buffer>,
+    vertex_buffer_capacity: u32,
     pub vertices: Vec<Vertex>,
     max_vertices: u32,
     vertices_allocated: u32,
-    pub index_buffer: wgpu::Buffer,
+    free_vertices: FreeList,
+    index_buffer: Option<wgpu::Buffer>,
+    index_buffer_capacity: u32,
     pub indices: Vec<u32>,
     max_indices: u32,
     indices_allocated: u32,
-    allocations: Vec<Allocation>,
-    dirty: bool,
+    free_indices: FreeList,
+    slots: Vec<Slot>,
+    draw_ranges: Vec<Range<u32>>,
+    draw_ranges_dirty: bool,
+    dirty_vertices: DirtyRanges,
+    dirty_indices: DirtyRanges,
 }
 
 impl DualBuffer {
-    pub fn new(device: &wgpu::Device, label: &str, max_vertices: u64, max_indices: u64) -> Self {
+    pub fn new(label: &str, max_vertices: u64, max_indices: u64) -> Self {
+        Self {
+            id: NEXT_BUFFER_ID.fetch_add(1, Ordering::Relaxed),
+            label: label.to_string(),
+            vertex_buffer: None,
+            vertex_buffer_capacity: 0,
+            vertices: Vec::with_capacity(max_vertices as usize),
+            max_vertices: max_vertices as u32,
+            vertices_allocated: 0,
+            free_vertices: FreeList::default(),
+            index_buffer: None,
+            index_buffer_capacity: 0,
+            indices: Vec::with_capacity(max_indices as usize),
+            max_indices: max_indices as u32,
+            indices_allocated: 0,
+            free_indices: FreeList::default(),
+            slots: Vec::new(),
+            draw_ranges: Vec::new(),
+            draw_ranges_dirty: false,
+            dirty_vertices: DirtyRanges::default(),
+            dirty_indices: DirtyRanges::default(),
+        }
+    }
+
+    fn create_vertex_buffer(device: &wgpu::Device, label: &str, max_vertices: u32) -> wgpu::Buffer {
         let vertex_size = std::mem::size_of::<Vertex>() as u64;
         let mut usage = wgpu::BufferUsage::VERTEX;
         usage.insert(wgpu::BufferUsage::COPY_DST);
-        let vertex_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+        device.create_buffer(&wgpu::BufferDescriptor {
             label: Some(&format!("{} Vertex Buffer", label)),
-            size: vertex_size * max_vertices,
+            size: vertex_size * max_vertices as u64,
             usage,
             mapped_at_creation: false,
-        });
+        })
+    }
 
+    fn create_index_buffer(device: &wgpu::Device, label: &str, max_indices: u32) -> wgpu::Buffer {
         let mut usage = wgpu::BufferUsage::INDEX;
         usage.insert(wgpu::BufferUsage::COPY_DST);
-        let index_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+        device.create_buffer(&wgpu::BufferDescriptor {
             label: Some(&format!("{} Index Buffer", label)),
-            size: 4 * max_indices,
+            size: 4 * max_indices as u64,
             usage,
             mapped_at_creation: false,
-        });
+        })
+    }
 
-        Self {
-            vertex_buffer,
-            vertices: vec![Vertex::default(); max_vertices as usize],
-            max_vertices: max_vertices as u32,
-            vertices_allocated: 0,
-            index_buffer,
-            indices: vec![0; max_indices as usize],
-            max_indices: max_indices as u32,
-            indices_allocated: 0,
-            allocations: Vec::new(),
-            dirty: false,
-        }
+    /// GPU vertex buffer, created by the first [`DualBuffer::write_buffer`].
+    pub fn vertex_buffer(&self) -> &wgpu::Buffer {
+        self.vertex_buffer
+            .as_ref()
+            .expect("Failed to get vertex buffer before the first upload.")
+    }
+
+    /// GPU index buffer, created by the first [`DualBuffer::write_buffer`].
+    pub fn index_buffer(&self) -> &wgpu::Buffer {
+        self.index_buffer
+            .as_ref()
+            .expect("Failed to get index buffer before the first upload.")
+    }
+
+    /// Number of vertices the buffers can hold before growing.
+    pub fn vertex_capacity(&self) -> u32 {
+        self.max_vertices
+    }
+
+    /// Number of indices the buffers can hold before growing.
+    pub fn index_capacity(&self) -> u32 {
+        self.max_indices
     }
 
     pub fn is_empty(&self) -> bool {
@@ -69,113 +295,773 @@ impl DualBuffer {
         self.indices_allocated
     }
 
-    /// Allocate sections of the buffers and return a handle index.
-    pub fn alloc(&mut self, num_vertices: u32, num_indices: u32) -> Result<usize, &'static str> {
-        if num_vertices <= (self.max_vertices - self.vertices_allocated)
-            && num_indices <= (self.max_indices - self.indices_allocated)
-        {
-            self.allocations.push(Allocation {
-                vertex_offset: self.vertices_allocated as u64,
-                num_vertices,
-                index_offset: self.indices_allocated as u64,
-                num_indices,
-            });
-            self.vertices_allocated += num_vertices;
-            self.indices_allocated += num_indices;
-            self.vertices
-                .resize(self.vertices_allocated as usize, Vertex::default());
-            self.indices.resize(self.indices_allocated as usize, 0);
-            Ok(self.allocations.len() - 1)
+    /// Allocate sections of the buffers and return a handle.
+    ///
+    /// Freed blocks are reused first, otherwise the allocation is placed after
+    /// the last allocated element.
+    pub fn alloc(
+        &mut self,
+        num_vertices: u32,
+        num_indices: u32,
+    ) -> Result<AllocationHandle, DualBufferError> {
+        let free_vertex_block = self.free_vertices.find(num_vertices);
+        let free_index_block = self.free_indices.find(num_indices);
+        let vertices_needed = if free_vertex_block.is_some() {
+            0
+        } else {
+            num_vertices
+        };
+        let indices_needed = if free_index_block.is_some() {
+            0
         } else {
-            Err("Not enough space for allocation.")
+            num_indices
+        };
+        let max_vertices = Self::grow(self.max_vertices, self.vertices_allocated, vertices_needed)
+            .ok_or(DualBufferError::OutOfVertices)?;
+        let max_indices = Self::grow(self.max_indices, self.indices_allocated, indices_needed)
+            .ok_or(DualBufferError::OutOfIndices)?;
+        self.max_vertices = max_vertices;
+        self.max_indices = max_indices;
+
+        let vertex_offset = match free_vertex_block {
+            Some(position) => self.free_vertices.take(position, num_vertices),
+            None => {
+                let offset = self.vertices_allocated;
+                self.vertices_allocated += num_vertices;
+                self.vertices
+                    .resize(self.vertices_allocated as usize, Vertex::default());
+                offset
+            }
+        };
+        let index_offset = match free_index_block {
+            Some(position) => self.free_indices.take(position, num_indices),
+            None => {
+                let offset = self.indices_allocated;
+                self.indices_allocated += num_indices;
+                self.indices.resize(self.indices_allocated as usize, 0);
+                offset
+            }
+        };
+
+        let allocation = Allocation {
+            vertex_offset,
+            num_vertices,
+            index_offset,
+            num_indices,
+            z_index: 0,
+        };
+        // Reuse the slot of a freed handle if there is one.
+        let slot = match self.slots.iter().position(|slot| slot.allocation.is_none()) {
+            Some(slot) => slot,
+            None => {
+                self.slots.push(Slot::default());
+                self.slots.len() - 1
+            }
+        };
+        self.slots[slot].allocation = Some(allocation);
+        self.draw_ranges_dirty = true;
+
+        Ok(AllocationHandle {
+            buffer_id: self.id,
+            slot: slot as u32,
+            generation: self.slots[slot].generation,
+        })
+    }
+
+    /// Look up the slot a handle refers to, checking that it is still live.
+    fn slot(&self, handle: AllocationHandle) -> Result<usize, DualBufferError> {
+        if handle.buffer_id != self.id {
+            return Err(DualBufferError::WrongBuffer);
+        }
+        match self.slots.get(handle.slot as usize) {
+            Some(slot) if slot.generation == handle.generation && slot.allocation.is_some() => {
+                Ok(handle.slot as usize)
+            }
+            _ => Err(DualBufferError::StaleHandle),
         }
     }
 
-    pub fn vertex_offset(&self, index: usize) -> u32 {
-        self.allocations[index].vertex_offset as u32
+    fn allocation(&self, handle: AllocationHandle) -> Result<&Allocation, DualBufferError> {
+        let slot = self.slot(handle)?;
+        Ok(self.slots[slot].allocation.as_ref().unwrap())
     }
 
-    pub fn get_mut_slice(&mut self, index: usize) -> Option<(&mut [Vertex], &mut [u32])> {
-        if index < self.allocations.len() {
-            let alloc = &self.allocations[index];
-            self.dirty = true;
+    /// Double `capacity` until `needed` more elements fit after `allocated`.
+    fn grow(capacity: u32, allocated: u32, needed: u32) -> Option<u32> {
+        let required = allocated.checked_add(needed)?;
+        if required <= capacity {
+            return Some(capacity);
+        }
+        let mut capacity = capacity.max(1);
+        while capacity < required {
+            capacity = capacity.checked_mul(2)?;
+        }
+        Some(capacity)
+    }
 
-            Some((
-                &mut self.vertices[(alloc.vertex_offset as usize)
-                    ..(alloc.vertex_offset as usize + alloc.num_vertices as usize)],
-                &mut self.indices[(alloc.index_offset as usize)
-                    ..(alloc.index_offset as usize + alloc.num_indices as usize)],
-            ))
-        } else {
-            None
+    /// Release an allocation so its blocks can be reused.
+    ///
+    /// The freed indices are zeroed so the stale geometry is no longer drawn.
+    pub fn free(&mut self, handle: AllocationHandle) -> Result<(), DualBufferError> {
+        let slot = self.slot(handle)?;
+        let slot = &mut self.slots[slot];
+        let alloc = slot.allocation.take().unwrap();
+        slot.generation = slot.generation.wrapping_add(1);
+        self.draw_ranges_dirty = true;
+
+        let index_range = (alloc.index_offset as usize)
+            ..(alloc.index_offset as usize + alloc.num_indices as usize);
+        for dst in &mut self.indices[index_range] {
+            *dst = 0;
         }
+        self.dirty_indices
+            .mark(alloc.index_offset..(alloc.index_offset + alloc.num_indices));
+
+        self.free_vertices
+            .insert(alloc.vertex_offset, alloc.num_vertices);
+        self.vertices_allocated = self.free_vertices.trim(self.vertices_allocated);
+        self.vertices.truncate(self.vertices_allocated as usize);
+
+        self.free_indices
+            .insert(alloc.index_offset, alloc.num_indices);
+        self.indices_allocated = self.free_indices.trim(self.indices_allocated);
+        self.indices.truncate(self.indices_allocated as usize);
+        Ok(())
+    }
+
+    /// Repack all live allocations to the start of the buffers.
+    ///
+    /// Handles stay valid. Indices are shifted to follow their vertices.
+    pub fn compact(&mut self) {
+        let mut live: Vec<usize> = (0..self.slots.len())
+            .filter(|&index| self.slots[index].allocation.is_some())
+            .collect();
+        let mut old_vertex_offsets = vec![0; self.slots.len()];
+
+        // Vertices
+        live.sort_by_key(|&index| self.slots[index].allocation.as_ref().unwrap().vertex_offset);
+        let mut next = 0;
+        for &index in &live {
+            let alloc = self.slots[index].allocation.as_mut().unwrap();
+            old_vertex_offsets[index] = alloc.vertex_offset;
+            let start = alloc.vertex_offset as usize;
+            let end = start + alloc.num_vertices as usize;
+            self.vertices.copy_within(start..end, next as usize);
+            alloc.vertex_offset = next;
+            next += alloc.num_vertices;
+        }
+        self.vertices_allocated = next;
+        self.vertices.truncate(next as usize);
+
+        // Indices
+        live.sort_by_key(|&index| self.slots[index].allocation.as_ref().unwrap().index_offset);
+        let mut next = 0;
+        for &index in &live {
+            let alloc = self.slots[index].allocation.as_mut().unwrap();
+            let start = alloc.index_offset as usize;
+            let end = start + alloc.num_indices as usize;
+            self.indices.copy_within(start..end, next as usize);
+            let old_vertex_offset = old_vertex_offsets[index];
+            let range = (next as usize)..(next as usize + alloc.num_indices as usize);
+            for dst in &mut self.indices[range] {
+                *dst = dst
+                    .wrapping_sub(old_vertex_offset)
+                    .wrapping_add(alloc.vertex_offset);
+            }
+            alloc.index_offset = next;
+            next += alloc.num_indices;
+        }
+        self.indices_allocated = next;
+        self.indices.truncate(next as usize);
+
+        self.free_vertices.clear();
+        self.free_indices.clear();
+        self.draw_ranges_dirty = true;
+        self.dirty_vertices.mark(0..self.vertices_allocated);
+        self.dirty_indices.mark(0..self.indices_allocated);
+    }
+
+    /// Set the layer of an allocation. Higher layers are drawn on top, equal
+    /// layers in buffer order.
+    pub fn set_z_index(
+        &mut self,
+        handle: AllocationHandle,
+        z_index: i32,
+    ) -> Result<(), DualBufferError> {
+        let slot = self.slot(handle)?;
+        let alloc = self.slots[slot].allocation.as_mut().unwrap();
+        if alloc.z_index != z_index {
+            alloc.z_index = z_index;
+            self.draw_ranges_dirty = true;
+        }
+        Ok(())
+    }
+
+    /// Index ranges to draw, in order, as of the last
+    /// [`DualBuffer::write_buffer`].
+    ///
+    /// Allocations are sorted by z index and neighbouring ranges merged, so
+    /// an unlayered buffer is drawn with a single call.
+    pub fn draw_ranges(&self) -> &[Range<u32>] {
+        &self.draw_ranges
+    }
+
+    fn update_draw_ranges(&mut self) {
+        if !self.draw_ranges_dirty {
+            return;
+        }
+        self.draw_ranges_dirty = false;
+
+        let mut allocations: Vec<&Allocation> = self
+            .slots
+            .iter()
+            .filter_map(|slot| slot.allocation.as_ref())
+            .filter(|alloc| alloc.num_indices > 0)
+            .collect();
+        allocations.sort_by_key(|alloc| (alloc.z_index, alloc.index_offset));
+
+        self.draw_ranges.clear();
+        for alloc in allocations {
+            let range = alloc.index_offset..(alloc.index_offset + alloc.num_indices);
+            match self.draw_ranges.last_mut() {
+                Some(last) if last.end == range.start => last.end = range.end,
+                _ => self.draw_ranges.push(range),
+            }
+        }
+    }
+
+    pub fn vertex_offset(&self, handle: AllocationHandle) -> Result<u32, DualBufferError> {
+        Ok(self.allocation(handle)?.vertex_offset)
+    }
+
+    pub fn get_mut_slice(
+        &mut self,
+        handle: AllocationHandle,
+    ) -> Result<(&mut [Vertex], &mut [u32]), DualBufferError> {
+        let slot = self.slot(handle)?;
+        let alloc = self.slots[slot].allocation.as_ref().unwrap();
+        let vertex_range = alloc.vertex_offset..(alloc.vertex_offset + alloc.num_vertices);
+        let index_range = alloc.index_offset..(alloc.index_offset + alloc.num_indices);
+        self.dirty_vertices.mark(vertex_range.clone());
+        self.dirty_indices.mark(index_range.clone());
+
+        Ok((
+            &mut self.vertices[(vertex_range.start as usize)..(vertex_range.end as usize)],
+            &mut self.indices[(index_range.start as usize)..(index_range.end as usize)],
+        ))
+    }
+
+    /// Like [`DualBuffer::get_mut_slice`] but only returns and marks as
+    /// changed the `len` vertices starting at vertex `first` of the
+    /// allocation.
+    fn vertices_mut(
+        &mut self,
+        handle: AllocationHandle,
+        first: u32,
+        len: u32,
+    ) -> Result<&mut [Vertex], DualBufferError> {
+        let slot = self.slot(handle)?;
+        let alloc = self.slots[slot].allocation.as_ref().unwrap();
+        let last = first
+            .checked_add(len)
+            .filter(|&last| last <= alloc.num_vertices)
+            .ok_or(DualBufferError::OutOfRange)?;
+        let start = alloc.vertex_offset + first;
+        let end = alloc.vertex_offset + last;
+        self.dirty_vertices.mark(start..end);
+        Ok(&mut self.vertices[(start as usize)..(end as usize)])
+    }
+
+    /// Like [`DualBuffer::get_mut_slice`] but only returns and marks as
+    /// changed the `len` indices starting at index `first` of the
+    /// allocation.
+    fn indices_mut(
+        &mut self,
+        handle: AllocationHandle,
+        first: u32,
+        len: u32,
+    ) -> Result<&mut [u32], DualBufferError> {
+        let slot = self.slot(handle)?;
+        let alloc = self.slots[slot].allocation.as_ref().unwrap();
+        let last = first
+            .checked_add(len)
+            .filter(|&last| last <= alloc.num_indices)
+            .ok_or(DualBufferError::OutOfRange)?;
+        let start = alloc.index_offset + first;
+        let end = alloc.index_offset + last;
+        self.dirty_indices.mark(start..end);
+        Ok(&mut self.indices[(start as usize)..(end as usize)])
     }
 
     /// Copy vertices into staging buffer and apply a translation.
     pub fn write_vertices_with_translation(
         &mut self,
-        index: usize,
+        handle: AllocationHandle,
         vertices: &[Vertex],
         translation: Point3D,
-    ) {
-        if let Some((dst_vertices, _dst_indices)) = self.get_mut_slice(index) {
-            for (src, dst) in vertices.iter().zip(dst_vertices.iter_mut()) {
-                *dst = Vertex {
-                    position: src.position + translation,
-                    color: src.color,
-                };
-            }
+    ) -> Result<(), DualBufferError> {
+        self.write_vertices_at(handle, 0, vertices, translation)
+    }
+
+    /// Like [`DualBuffer::write_vertices_with_translation`] but starts at
+    /// vertex `first` of the allocation and leaves the others untouched.
+    pub fn write_vertices_at(
+        &mut self,
+        handle: AllocationHandle,
+        first: u32,
+        vertices: &[Vertex],
+        translation: Point3D,
+    ) -> Result<(), DualBufferError> {
+        let dst_vertices = self.vertices_mut(handle, first, vertices.len() as u32)?;
+        for (src, dst) in vertices.iter().zip(dst_vertices.iter_mut()) {
+            *dst = Vertex {
+                position: src.position + translation,
+                ..*src
+            };
         }
+        Ok(())
     }
 
     /// Copy indices into staging buffer.
-    pub fn write_indices(&mut self, index: usize, indices: &[u32]) {
-        let vo = self.vertex_offset(index);
-        if let Some((_dst_vertices, dst_indices)) = self.get_mut_slice(index) {
-            for (src, dst) in indices.iter().zip(dst_indices.iter_mut()) {
-                *dst = src + vo;
-            }
+    pub fn write_indices(
+        &mut self,
+        handle: AllocationHandle,
+        indices: &[u32],
+    ) -> Result<(), DualBufferError> {
+        self.write_indices_at(handle, 0, indices)
+    }
+
+    /// Like [`DualBuffer::write_indices`] but starts at index `first` of the
+    /// allocation and leaves the others untouched.
+    pub fn write_indices_at(
+        &mut self,
+        handle: AllocationHandle,
+        first: u32,
+        indices: &[u32],
+    ) -> Result<(), DualBufferError> {
+        let vo = self.vertex_offset(handle)?;
+        let dst_indices = self.indices_mut(handle, first, indices.len() as u32)?;
+        for (src, dst) in indices.iter().zip(dst_indices.iter_mut()) {
+            *dst = src + vo;
         }
+        Ok(())
     }
 
-    /// Write the vertices and indices into GPU memory using a staging belt.
+    /// Byte ranges of the vertex and index buffers changed since the last
+    /// upload, merged and clipped to the allocated elements.
+    ///
+    /// The tracked ranges are cleared.
+    fn take_upload_ranges(&mut self) -> (Vec<Range<u64>>, Vec<Range<u64>>) {
+        let vertex_size = std::mem::size_of::<Vertex>() as u64;
+        let index_size = std::mem::size_of::<u32>() as u64;
+        let vertices = self
+            .dirty_vertices
+            .take_merged(self.vertices_allocated)
+            .iter()
+            .map(|range| byte_range(range, vertex_size))
+            .collect();
+        let indices = self
+            .dirty_indices
+            .take_merged(self.indices_allocated)
+            .iter()
+            .map(|range| byte_range(range, index_size))
+            .collect();
+        (vertices, indices)
+    }
+
+    /// Write the changed vertices and indices into GPU memory using a staging
+    /// belt.
+    ///
+    /// Changed ranges are merged and each merged range is uploaded separately.
     pub fn write_buffer(
         &mut self,
         staging_belt: &mut wgpu::util::StagingBelt,
         encoder: &mut wgpu::CommandEncoder,
         device: &wgpu::Device,
-    ) {
-        if self.is_empty() || !self.dirty {
-            return;
+    ) -> u64 {
+        self.update_draw_ranges();
+        let mut uploaded = 0;
+
+        // Create the GPU buffers, or recreate any that allocations have
+        // outgrown. The staging vectors hold everything, so a full upload
+        // refills the new buffers.
+        if self.vertex_buffer.is_none() || self.vertex_buffer_capacity < self.max_vertices {
+            self.vertex_buffer = Some(Self::create_vertex_buffer(
+                device,
+                &self.label,
+                self.max_vertices,
+            ));
+            self.vertex_buffer_capacity = self.max_vertices;
+            self.dirty_vertices.mark(0..self.vertices_allocated);
+        }
+        if self.index_buffer.is_none() || self.index_buffer_capacity < self.max_indices {
+            self.index_buffer = Some(Self::create_index_buffer(
+                device,
+                &self.label,
+                self.max_indices,
+            ));
+            self.index_buffer_capacity = self.max_indices;
+            self.dirty_indices.mark(0..self.indices_allocated);
         }
-        self.dirty = false;
+        let (vertex_ranges, index_ranges) = self.take_upload_ranges();
+        let vertex_buffer = self.vertex_buffer.as_ref().unwrap();
+        let index_buffer = self.index_buffer.as_ref().unwrap();
 
-        // Vertices
-        {
-            let size = self.vertices_allocated as u64 * std::mem::size_of::<Vertex>() as u64;
-            let mut buf_view = staging_belt.write_buffer(
-                &mut *encoder,
-                &self.vertex_buffer,
-                0,
-                wgpu::BufferSize::new(size).unwrap(),
-                &device,
-            );
+        let uploads = [
+            (
+                vertex_buffer,
+                vertex_ranges,
+                bytemuck::cast_slice(&self.vertices),
+            ),
+            (
+                index_buffer,
+                index_ranges,
+                bytemuck::cast_slice(&self.indices),
+            ),
+        ];
+        for (buffer, ranges, data) in &uploads {
+            for bytes in ranges {
+                uploaded += bytes.end - bytes.start;
+                let mut buf_view = staging_belt.write_buffer(
+                    &mut *encoder,
+                    buffer,
+                    bytes.start,
+                    wgpu::BufferSize::new(bytes.end - bytes.start).unwrap(),
+                    &device,
+                );
 
-            buf_view.copy_from_slice(bytemuck::cast_slice(&self.vertices));
+                buf_view.copy_from_slice(&data[(bytes.start as usize)..(bytes.end as usize)]);
+            }
         }
 
-        // Indices
-        {
-            let size = self.indices_allocated as u64 * 4;
-            let mut buf_view = staging_belt.write_buffer(
-                &mut *encoder,
-                &self.index_buffer,
-                0,
-                wgpu::BufferSize::new(size).unwrap(),
-                &device,
-            );
+        uploaded
+    }
+}
 
-            buf_view.copy_from_slice(bytemuck::cast_slice(&self.indices));
-        }
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const QUAD: [u32; 6] = [0, 1, 2, 0, 2, 3];
+
+    fn bytes(ranges: &[(u64, u64)]) -> Vec<Range<u64>> {
+        ranges.iter().map(|&(start, end)| start..end).collect()
+    }
+
+    fn offsets(list: &FreeList) -> Vec<(u32, u32)> {
+        list.blocks
+            .iter()
+            .map(|block| (block.offset, block.len))
+            .collect()
+    }
+
+    #[test]
+    fn free_list_merges_neighbours() {
+        let mut list = FreeList::default();
+        list.insert(0, 4);
+        list.insert(8, 4);
+        list.insert(20, 2);
+        assert_eq!(offsets(&list), vec![(0, 4), (8, 4), (20, 2)]);
+
+        list.insert(4, 4);
+        assert_eq!(offsets(&list), vec![(0, 12), (20, 2)]);
+        list.insert(12, 8);
+        assert_eq!(offsets(&list), vec![(0, 22)]);
+    }
+
+    #[test]
+    fn free_list_takes_from_the_first_fit() {
+        let mut list = FreeList::default();
+        list.insert(0, 2);
+        list.insert(10, 6);
+        assert_eq!(list.find(0), None);
+        assert_eq!(list.find(7), None);
+
+        let position = list.find(4).unwrap();
+        assert_eq!(list.take(position, 4), 10);
+        assert_eq!(offsets(&list), vec![(0, 2), (14, 2)]);
+        let position = list.find(2).unwrap();
+        assert_eq!(list.take(position, 2), 0);
+        assert_eq!(offsets(&list), vec![(14, 2)]);
+    }
+
+    #[test]
+    fn free_list_trims_the_last_block() {
+        let mut list = FreeList::default();
+        list.insert(4, 4);
+        assert_eq!(list.trim(12), 12);
+        assert_eq!(list.trim(8), 4);
+        assert!(list.blocks.is_empty());
+    }
+
+    #[test]
+    fn allocations_are_placed_back_to_back() {
+        let mut buffer = DualBuffer::new("Test", 16, 16);
+        let a = buffer.alloc(4, 6).unwrap();
+        let b = buffer.alloc(3, 3).unwrap();
+
+        assert_eq!(buffer.vertex_offset(a), Ok(0));
+        assert_eq!(buffer.vertex_offset(b), Ok(4));
+        assert_eq!(buffer.indices_len(), 9);
+        assert_eq!(buffer.vertices.len(), 7);
+    }
+
+    #[test]
+    fn allocations_grow_the_capacity() {
+        let mut buffer = DualBuffer::new("Test", 4, 4);
+        buffer.alloc(3, 3).unwrap();
+        buffer.alloc(3, 3).unwrap();
+        assert_eq!(buffer.vertex_capacity(), 8);
+        assert_eq!(buffer.index_capacity(), 8);
+    }
+
+    #[test]
+    fn freed_blocks_are_reused() {
+        let mut buffer = DualBuffer::new("Test", 16, 16);
+        let a = buffer.alloc(4, 6).unwrap();
+        let _b = buffer.alloc(4, 6).unwrap();
+        buffer.free(a).unwrap();
+
+        let c = buffer.alloc(2, 3).unwrap();
+        assert_eq!(buffer.vertex_offset(c), Ok(0));
+        assert_eq!(buffer.vertices.len(), 8);
+    }
+
+    #[test]
+    fn neighbouring_freed_blocks_merge() {
+        let mut buffer = DualBuffer::new("Test", 16, 16);
+        let a = buffer.alloc(4, 6).unwrap();
+        let b = buffer.alloc(4, 6).unwrap();
+        let _c = buffer.alloc(4, 6).unwrap();
+        buffer.free(a).unwrap();
+        buffer.free(b).unwrap();
+        assert_eq!(offsets(&buffer.free_vertices), vec![(0, 8)]);
+        assert_eq!(offsets(&buffer.free_indices), vec![(0, 12)]);
+
+        // The merged block fits an allocation neither half could hold.
+        let d = buffer.alloc(8, 12).unwrap();
+        assert_eq!(buffer.vertex_offset(d), Ok(0));
+        assert_eq!(buffer.indices_len(), 18);
+    }
+
+    #[test]
+    fn freeing_the_last_allocation_shrinks_the_buffers() {
+        let mut buffer = DualBuffer::new("Test", 16, 16);
+        let a = buffer.alloc(4, 6).unwrap();
+        let b = buffer.alloc(4, 6).unwrap();
+        buffer.free(a).unwrap();
+        buffer.free(b).unwrap();
+
+        assert!(buffer.is_empty());
+        assert_eq!(buffer.indices_len(), 0);
+        assert!(buffer.vertices.is_empty());
+        assert!(buffer.free_vertices.blocks.is_empty());
+    }
+
+    #[test]
+    fn freed_indices_are_zeroed() {
+        let mut buffer = DualBuffer::new("Test", 16, 16);
+        let a = buffer.alloc(4, 6).unwrap();
+        let b = buffer.alloc(4, 6).unwrap();
+        buffer.write_indices(a, &QUAD).unwrap();
+        buffer.write_indices(b, &QUAD).unwrap();
+        buffer.free(a).unwrap();
+
+        assert_eq!(buffer.indices[..6], [0; 6]);
+        assert_eq!(buffer.indices[6..], [4, 5, 6, 4, 6, 7]);
+    }
+
+    #[test]
+    fn compact_moves_indices_with_their_vertices() {
+        let mut buffer = DualBuffer::new("Test", 32, 32);
+        let a = buffer.alloc(4, 6).unwrap();
+        let b = buffer.alloc(4, 6).unwrap();
+        let c = buffer.alloc(3, 3).unwrap();
+        buffer.write_indices(a, &QUAD).unwrap();
+        buffer.write_indices(b, &QUAD).unwrap();
+        buffer.write_indices(c, &[0, 1, 2]).unwrap();
+        let vertex = Vertex {
+            position: Point3D {
+                x: 1.0,
+                y: 2.0,
+                z: 0.0,
+            },
+            ..Vertex::default()
+        };
+        buffer
+            .write_vertices_with_translation(c, &[vertex; 3], Point3D::default())
+            .unwrap();
+        buffer.free(a).unwrap();
+        buffer.compact();
+
+        assert_eq!(buffer.vertex_offset(b), Ok(0));
+        assert_eq!(buffer.vertex_offset(c), Ok(4));
+        assert_eq!(buffer.indices, vec![0, 1, 2, 0, 2, 3, 4, 5, 6]);
+        assert_eq!(buffer.vertices.len(), 7);
+        assert_eq!(buffer.vertices[4].position.y, 2.0);
+        assert!(buffer.free_vertices.blocks.is_empty());
+        assert!(buffer.free_indices.blocks.is_empty());
+        buffer.update_draw_ranges();
+        assert_eq!(buffer.draw_ranges().len(), 1);
+        assert_eq!(buffer.draw_ranges()[0], 0..9);
+    }
+
+    #[test]
+    fn stale_handles_are_rejected() {
+        let mut buffer = DualBuffer::new("Test", 16, 16);
+        let a = buffer.alloc(4, 6).unwrap();
+        buffer.free(a).unwrap();
+
+        assert_eq!(buffer.free(a), Err(DualBufferError::StaleHandle));
+        assert_eq!(buffer.vertex_offset(a), Err(DualBufferError::StaleHandle));
+        assert_eq!(
+            buffer.write_indices(a, &QUAD),
+            Err(DualBufferError::StaleHandle)
+        );
+
+        // The slot is reused, but the old handle still refers to the freed
+        // allocation.
+        let b = buffer.alloc(4, 6).unwrap();
+        assert_eq!(b.slot, a.slot);
+        assert_eq!(buffer.set_z_index(a, 1), Err(DualBufferError::StaleHandle));
+        assert_eq!(buffer.free(a), Err(DualBufferError::StaleHandle));
+        assert_eq!(buffer.free(b), Ok(()));
+    }
+
+    #[test]
+    fn handles_from_other_buffers_are_rejected() {
+        let mut buffer = DualBuffer::new("Test", 16, 16);
+        let mut other = DualBuffer::new("Other", 16, 16);
+        let _a = buffer.alloc(4, 6).unwrap();
+        let b = other.alloc(4, 6).unwrap();
+
+        assert_eq!(buffer.vertex_offset(b), Err(DualBufferError::WrongBuffer));
+        assert_eq!(buffer.free(b), Err(DualBufferError::WrongBuffer));
+        assert!(matches!(
+            buffer.get_mut_slice(b),
+            Err(DualBufferError::WrongBuffer)
+        ));
+        assert_eq!(other.free(b), Ok(()));
+    }
+
+    #[test]
+    fn writes_past_the_allocation_are_rejected() {
+        let mut buffer = DualBuffer::new("Test", 16, 16);
+        let a = buffer.alloc(4, 6).unwrap();
+        let b = buffer.alloc(4, 6).unwrap();
+        buffer.take_upload_ranges();
+
+        let vertex = Vertex::default();
+        let origin = Point3D::default();
+        assert_eq!(
+            buffer.write_vertices_at(a, 2, &[vertex; 3], origin),
+            Err(DualBufferError::OutOfRange)
+        );
+        assert_eq!(
+            buffer.write_vertices_at(a, u32::MAX, &[vertex; 2], origin),
+            Err(DualBufferError::OutOfRange)
+        );
+        assert_eq!(
+            buffer.write_indices_at(a, 4, &[0, 1, 2]),
+            Err(DualBufferError::OutOfRange)
+        );
+        assert_eq!(
+            buffer.write_indices(a, &[0; 7]),
+            Err(DualBufferError::OutOfRange)
+        );
+
+        // Nothing was written, so the next allocation is untouched.
+        assert_eq!(buffer.take_upload_ranges(), (vec![], vec![]));
+        assert_eq!(buffer.write_vertices_at(a, 2, &[vertex; 2], origin), Ok(()));
+        assert_eq!(buffer.write_indices_at(a, 4, &[0, 1]), Ok(()));
+        assert_eq!(buffer.free(b), Ok(()));
+    }
+
+    #[test]
+    fn adjacent_and_overlapping_ranges_merge() {
+        let mut dirty = DirtyRanges::default();
+        dirty.mark(8..12);
+        dirty.mark(0..4);
+        dirty.mark(4..6);
+        dirty.mark(10..16);
+        dirty.mark(20..24);
+
+        assert_eq!(dirty.take_merged(100), vec![0..6, 8..16, 20..24]);
+        assert!(dirty.take_merged(100).is_empty());
+    }
+
+    #[test]
+    fn ranges_are_clipped_to_allocated_length() {
+        let mut dirty = DirtyRanges::default();
+        dirty.mark(0..4);
+        dirty.mark(6..10);
+        dirty.mark(12..16);
+
+        assert_eq!(dirty.take_merged(8), vec![0..4, 6..8]);
+    }
+
+    #[test]
+    fn empty_ranges_are_ignored() {
+        let mut dirty = DirtyRanges::default();
+        dirty.mark(5..5);
+
+        assert!(dirty.take_merged(100).is_empty());
+    }
+
+    #[test]
+    fn edits_emit_byte_ranges() {
+        let vertex_size = std::mem::size_of::<Vertex>() as u64;
+        let mut buffer = DualBuffer::new("Test", 64, 64);
+        let rectangle = buffer.alloc(4, 6).unwrap();
+        let polygon = buffer.alloc(11, 30).unwrap();
+        let line = buffer.alloc(2, 2).unwrap();
+        assert_eq!(buffer.take_upload_ranges(), (vec![], vec![]));
+
+        // Moving the rectangle and the line uploads two vertex ranges.
+        let vertex = Vertex::default();
+        let origin = Point3D::default();
+        buffer
+            .write_vertices_with_translation(rectangle, &[vertex; 4], origin)
+            .unwrap();
+        buffer
+            .write_vertices_with_translation(line, &[vertex; 2], origin)
+            .unwrap();
+        assert_eq!(
+            buffer.take_upload_ranges(),
+            (
+                bytes(&[(0, 4 * vertex_size), (15 * vertex_size, 17 * vertex_size)]),
+                vec![]
+            )
+        );
+
+        // Editing the rectangle and the polygon next to it is one upload
+        // each for vertices and indices.
+        buffer
+            .write_vertices_with_translation(rectangle, &[vertex; 4], origin)
+            .unwrap();
+        buffer
+            .write_vertices_with_translation(polygon, &[vertex; 11], origin)
+            .unwrap();
+        buffer.write_indices(rectangle, &QUAD).unwrap();
+        buffer.write_indices(polygon, &[0; 30]).unwrap();
+        assert_eq!(
+            buffer.take_upload_ranges(),
+            (bytes(&[(0, 15 * vertex_size)]), bytes(&[(0, 36 * 4)]))
+        );
+
+        // Freeing the polygon zeroes its indices, which are uploaded. Its
+        // vertices are left alone.
+        buffer.free(polygon).unwrap();
+        assert_eq!(
+            buffer.take_upload_ranges(),
+            (vec![], bytes(&[(6 * 4, 36 * 4)]))
+        );
+
+        // Freeing the line at the end clips its range away.
+        buffer
+            .write_vertices_with_translation(line, &[vertex; 2], origin)
+            .unwrap();
+        buffer.free(line).unwrap();
+        assert_eq!(buffer.take_upload_ranges(), (vec![], vec![]));
     }
 }
@@ -0,0 +1,245 @@
+use crate::shape::Size;
+use std::fmt;
+use std::path::Path;
+use wgpu_glyph::ab_glyph::{Font, FontArc, InvalidFont, PxScale, ScaleFont};
+use wgpu_glyph::{FontId, GlyphPositioner, Layout, SectionGeometry, SectionText};
+
+/// Errors returned when loading a font.
+#[derive(Debug)]
+pub enum FontError {
+    Io(std::io::Error),
+    Invalid(InvalidFont),
+}
+
+impl fmt::Display for FontError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            FontError::Io(err) => write!(f, "failed to read font: {}", err),
+            FontError::Invalid(err) => write!(f, "failed to parse font: {}", err),
+        }
+    }
+}
+
+impl std::error::Error for FontError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            FontError::Io(err) => Some(err),
+            FontError::Invalid(err) => Some(err),
+        }
+    }
+}
+
+impl From<std::io::Error> for FontError {
+    fn from(err: std::io::Error) -> Self {
+        FontError::Io(err)
+    }
+}
+
+impl From<InvalidFont> for FontError {
+    fn from(err: InvalidFont) -> Self {
+        FontError::Invalid(err)
+    }
+}
+
+/// Loaded fonts and the fallback chain used for missing glyphs.
+///
+/// The ids match the fonts of the renderer's glyph brush. The built in font is
+/// always [`FontRegistry::DEFAULT`].
+///
+/// Measuring text only needs the registry, so a clone can be moved into
+/// layout code such as stretch measure functions.
+#[derive(Clone)]
+pub struct FontRegistry {
+    fonts: Vec<FontArc>,
+    fallbacks: Vec<FontId>,
+}
+
+impl FontRegistry {
+    pub const DEFAULT: FontId = FontId(0);
+
+    pub fn new(default_font: FontArc) -> Self {
+        Self {
+            fonts: vec![default_font],
+            fallbacks: Vec::new(),
+        }
+    }
+
+    /// Parse a TTF or OTF font from bytes.
+    pub fn parse(bytes: Vec<u8>) -> Result<FontArc, FontError> {
+        Ok(FontArc::try_from_vec(bytes)?)
+    }
+
+    /// Parse a TTF or OTF font from a static byte slice without copying it.
+    pub fn parse_static(bytes: &'static [u8]) -> Result<FontArc, FontError> {
+        Ok(FontArc::try_from_slice(bytes)?)
+    }
+
+    /// Read and parse a TTF or OTF font file.
+    pub fn parse_file<P: AsRef<Path>>(path: P) -> Result<FontArc, FontError> {
+        Self::parse(std::fs::read(path)?)
+    }
+
+    /// Add a font and return its id.
+    ///
+    /// Use [`crate::Renderer::add_font`] so the glyph brush gets the font too.
+    pub fn add(&mut self, font: FontArc) -> FontId {
+        self.fonts.push(font);
+        FontId(self.fonts.len() - 1)
+    }
+
+    pub fn get(&self, id: FontId) -> Option<&FontArc> {
+        self.fonts.get(id.0)
+    }
+
+    pub fn fonts(&self) -> &[FontArc] {
+        &self.fonts
+    }
+
+    /// Fonts tried in order when a glyph is missing from the requested font.
+    pub fn set_fallbacks(&mut self, fallbacks: Vec<FontId>) {
+        self.fallbacks = fallbacks
+            .into_iter()
+            .filter(|id| id.0 < self.fonts.len())
+            .collect();
+    }
+
+    pub fn fallbacks(&self) -> &[FontId] {
+        &self.fallbacks
+    }
+
+    /// The first font in the chain of `font` and the fallbacks that has a
+    /// glyph for `c`. Falls back to `font` itself if none do.
+    pub fn font_for_char(&self, font: FontId, c: char) -> FontId {
+        std::iter::once(font)
+            .chain(self.fallbacks.iter().copied())
+            .find(|id| self.get(*id).is_some_and(|font| font.glyph_id(c).0 != 0))
+            .unwrap_or(font)
+    }
+
+    /// Split `text` into runs that each use a single font from the chain.
+    ///
+    /// Whitespace stays in the current run so words aren't split needlessly.
+    pub fn runs<'a>(&self, text: &'a str, font: FontId) -> Vec<(&'a str, FontId)> {
+        let mut runs = Vec::new();
+        let mut start = 0;
+        let mut current = None;
+        for (i, c) in text.char_indices() {
+            if c.is_whitespace() && current.is_some() {
+                continue;
+            }
+            let id = self.font_for_char(font, c);
+            match current {
+                Some(current_id) if current_id != id => {
+                    runs.push((&text[start..i], current_id));
+                    start = i;
+                    current = Some(id);
+                }
+                None => current = Some(id),
+                _ => {}
+            }
+        }
+        if start < text.len() {
+            runs.push((&text[start..], current.unwrap_or(font)));
+        }
+        runs
+    }
+
+    /// Size in pixels of `text` as the glyph brush would lay it out, measured
+    /// from the top left of the section to the end of the furthest advance.
+    ///
+    /// With a `max_width` words wrap at that width, like a bounded
+    /// [`crate::Text`]. Fallback fonts are used for missing glyphs.
+    pub fn measure_text(
+        &self,
+        text: &str,
+        font: FontId,
+        scale: f32,
+        max_width: Option<f32>,
+    ) -> Size {
+        let runs = self.runs(text, font);
+        let sections: Vec<SectionText> = runs
+            .iter()
+            .map(|&(text, font_id)| SectionText {
+                text,
+                scale: PxScale::from(scale),
+                font_id,
+            })
+            .collect();
+        let geometry = SectionGeometry {
+            screen_position: (0.0, 0.0),
+            bounds: (max_width.unwrap_or(f32::INFINITY), f32::INFINITY),
+        };
+
+        // Union of the advance boxes, which is what the layout steps by.
+        let mut width: f32 = 0.0;
+        let mut height: f32 = 0.0;
+        for glyph in Layout::default_wrap().calculate_glyphs(&self.fonts, &geometry, &sections) {
+            let font = self.fonts[glyph.font_id.0].as_scaled(glyph.glyph.scale);
+            let position = glyph.glyph.position;
+            width = width.max(position.x + font.h_advance(glyph.glyph.id));
+            height = height.max(position.y - font.descent());
+        }
+
+        Size { width, height }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn registry() -> FontRegistry {
+        FontRegistry::new(FontRegistry::parse_static(include_bytes!("UbuntuMono-R.ttf")).unwrap())
+    }
+
+    #[test]
+    fn empty_text_has_no_size() {
+        let size = registry().measure_text("", FontRegistry::DEFAULT, 20.0, None);
+        assert_eq!(size, Size::default());
+    }
+
+    #[test]
+    fn single_line_uses_advances_and_line_height() {
+        let fonts = registry();
+        let font = fonts.get(FontRegistry::DEFAULT).unwrap().as_scaled(20.0);
+        let advance = font.h_advance(font.glyph_id('a'));
+
+        let size = fonts.measure_text("abcd", FontRegistry::DEFAULT, 20.0, None);
+        assert!((size.width - 4.0 * advance).abs() < 0.01);
+        assert!((size.height - (font.ascent() - font.descent())).abs() < 0.01);
+    }
+
+    #[test]
+    fn words_wrap_at_max_width() {
+        let fonts = registry();
+        let one_line = fonts.measure_text("aaaa bbbb", FontRegistry::DEFAULT, 20.0, None);
+        let word = fonts.measure_text("aaaa ", FontRegistry::DEFAULT, 20.0, None);
+
+        let wrapped = fonts.measure_text(
+            "aaaa bbbb",
+            FontRegistry::DEFAULT,
+            20.0,
+            Some(word.width + 1.0),
+        );
+        assert!(wrapped.width <= word.width + 1.0);
+        assert!(wrapped.height > one_line.height * 1.5);
+    }
+
+    #[test]
+    fn newlines_add_lines() {
+        let fonts = registry();
+        let one_line = fonts.measure_text("a", FontRegistry::DEFAULT, 20.0, None);
+        let two_lines = fonts.measure_text("a\nb", FontRegistry::DEFAULT, 20.0, None);
+        assert_eq!(two_lines.width, one_line.width);
+        assert!(two_lines.height > one_line.height * 1.5);
+    }
+
+    #[test]
+    fn runs_without_fallbacks_use_one_font() {
+        let fonts = registry();
+        assert_eq!(
+            fonts.runs("a b", FontRegistry::DEFAULT),
+            vec![("a b", FontRegistry::DEFAULT)]
+        );
+    }
+}
@@ -1,13 +1,80 @@
-use crate::{ DualBuffer, Vertex, Point2, Color};
+use super::textures::Textures;
+use crate::shape::Size;
+use crate::{
+    AllocationHandle, Background, BackgroundError, Color, DualBuffer, FontError, FontRegistry,
+    Point2, Point3D, TextSections, Vector2, Vertex,
+};
+use crate::{AtlasConfig, TextureError, TextureId, TextureRect, UvRect};
 use futures::task::SpawnExt;
-use wgpu_glyph::{ab_glyph, GlyphBrush, GlyphBrushBuilder, Section, Text};
+use std::path::Path;
+use wgpu_glyph::{ab_glyph, FontId, GlyphBrush, GlyphBrushBuilder, Section, Text};
 use winit::{event::*, window::Window};
 
-const TRIANGLES_MAX_VERTICES: u64 = 100;
-const TRIANGLES_MAX_INDICES: u64 = 320;
+/// Settings used when building a [`Renderer`].
+///
+/// The buffer sizes are initial capacities. Buffers grow when they fill up.
+#[derive(Clone, Debug)]
+pub struct RendererConfig {
+    pub triangles_vertices: u64,
+    pub triangles_indices: u64,
+    pub lines_vertices: u64,
+    pub lines_indices: u64,
+    pub antialiasing: Antialiasing,
+    /// Page size, spacing and page limit of the texture atlas images are
+    /// added to.
+    pub atlas: AtlasConfig,
+}
+
+impl Default for RendererConfig {
+    fn default() -> Self {
+        Self {
+            triangles_vertices: 1024,
+            triangles_indices: 4096,
+            lines_vertices: 256,
+            lines_indices: 512,
+            antialiasing: Antialiasing::None,
+            atlas: AtlasConfig::default(),
+        }
+    }
+}
+
+/// How shape edges are smoothed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Antialiasing {
+    None,
+    /// Multisampling with 2, 4 or 8 samples per pixel, resolved every frame.
+    Msaa2,
+    Msaa4,
+    Msaa8,
+    /// A one pixel wide transparent fringe around polygons and rectangles,
+    /// built on the CPU. Cheaper than multisampling on low-end adapters.
+    Feathered,
+}
+
+impl Antialiasing {
+    pub fn sample_count(self) -> u32 {
+        match self {
+            Antialiasing::Msaa2 => 2,
+            Antialiasing::Msaa4 => 4,
+            Antialiasing::Msaa8 => 8,
+            Antialiasing::None | Antialiasing::Feathered => 1,
+        }
+    }
+}
 
-const LINES_MAX_VERTICES: u64 = 50;
-const LINES_MAX_INDICES: u64 = 100;
+/// Work recorded for the last finished frame.
+///
+/// Text is drawn by the glyph brush in a second pass after the shapes, since
+/// wgpu_glyph 0.10 records a render pass of its own and can't draw into the
+/// shapes' pass. Frames without text skip it and use a single pass.
+/// Glyph cache uploads are not included in `uploaded_bytes`, but images
+/// added since the previous frame are.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct FrameStats {
+    pub render_passes: u32,
+    pub draw_calls: u32,
+    pub uploaded_bytes: u64,
+}
 
 /// Create a new render pipeline with shaders and primitive topology.
 fn create_pipeline(
@@ -16,10 +83,12 @@ fn create_pipeline(
     fs_module: &wgpu::ShaderModule,
     format: wgpu::TextureFormat,
     primitive_topology: wgpu::PrimitiveTopology,
+    sample_count: u32,
+    textures_layout: &wgpu::BindGroupLayout,
 ) -> wgpu::RenderPipeline {
     let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
         label: None,
-        bind_group_layouts: &[],
+        bind_group_layouts: &[textures_layout],
         push_constant_ranges: &[],
     });
 
@@ -38,8 +107,17 @@ fn create_pipeline(
         primitive_topology,
         color_states: &[wgpu::ColorStateDescriptor {
             format,
-            color_blend: wgpu::BlendDescriptor::REPLACE,
-            alpha_blend: wgpu::BlendDescriptor::REPLACE,
+            // Colours are premultiplied by the fragment shader.
+            color_blend: wgpu::BlendDescriptor {
+                src_factor: wgpu::BlendFactor::One,
+                dst_factor: wgpu::BlendFactor::OneMinusSrcAlpha,
+                operation: wgpu::BlendOperation::Add,
+            },
+            alpha_blend: wgpu::BlendDescriptor {
+                src_factor: wgpu::BlendFactor::One,
+                dst_factor: wgpu::BlendFactor::OneMinusSrcAlpha,
+                operation: wgpu::BlendOperation::Add,
+            },
             write_mask: wgpu::ColorWrite::ALL,
         }],
         depth_stencil_state: None,
@@ -47,7 +125,7 @@ fn create_pipeline(
             index_format: wgpu::IndexFormat::Uint32,
             vertex_buffers: &[Vertex::desc()],
         },
-        sample_count: 1,
+        sample_count,
         sample_mask: !0,
         alpha_to_coverage_enabled: false,
     });
@@ -55,31 +133,127 @@ fn create_pipeline(
     render_pipeline
 }
 
+/// Where a [`Renderer`] draws its frames.
+enum Target {
+    /// A window surface presented through a swap chain.
+    Window {
+        surface: wgpu::Surface,
+        sc_desc: wgpu::SwapChainDescriptor,
+        swap_chain: wgpu::SwapChain,
+    },
+    /// An offscreen texture that can be read back.
+    Texture {
+        texture: wgpu::Texture,
+        view: wgpu::TextureView,
+    },
+}
+
+/// Create the multisampled texture shapes are drawn into before being
+/// resolved into the frame.
+fn create_multisampled_framebuffer(
+    device: &wgpu::Device,
+    format: wgpu::TextureFormat,
+    size: winit::dpi::PhysicalSize<u32>,
+    sample_count: u32,
+) -> wgpu::TextureView {
+    device
+        .create_texture(&wgpu::TextureDescriptor {
+            label: Some("Multisampled Framebuffer"),
+            size: wgpu::Extent3d {
+                width: size.width,
+                height: size.height,
+                depth: 1,
+            },
+            mip_level_count: 1,
+            sample_count,
+            dimension: wgpu::TextureDimension::D2,
+            format,
+            usage: wgpu::TextureUsage::OUTPUT_ATTACHMENT,
+        })
+        .create_view(&wgpu::TextureViewDescriptor::default())
+}
+
+/// Create an offscreen texture to render into and copy pixels out of.
+fn create_target_texture(
+    device: &wgpu::Device,
+    format: wgpu::TextureFormat,
+    size: winit::dpi::PhysicalSize<u32>,
+) -> (wgpu::Texture, wgpu::TextureView) {
+    let mut usage = wgpu::TextureUsage::OUTPUT_ATTACHMENT;
+    usage.insert(wgpu::TextureUsage::COPY_SRC);
+    let texture = device.create_texture(&wgpu::TextureDescriptor {
+        label: Some("Headless Target Texture"),
+        size: wgpu::Extent3d {
+            width: size.width,
+            height: size.height,
+            depth: 1,
+        },
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: wgpu::TextureDimension::D2,
+        format,
+        usage,
+    });
+    let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+
+    (texture, view)
+}
+
+/// The frame being drawn. Headless renderers draw straight into their target.
+enum Frame {
+    SwapChain(wgpu::SwapChainTexture),
+    Texture,
+}
+
+impl Frame {
+    fn view<'a>(&'a self, target: &'a Target) -> &'a wgpu::TextureView {
+        match (self, target) {
+            (Frame::SwapChain(frame), _) => &frame.view,
+            (Frame::Texture, Target::Texture { view, .. }) => view,
+            (Frame::Texture, Target::Window { .. }) => unreachable!(),
+        }
+    }
+}
+
 struct RenderInProgress {
-    frame: wgpu::SwapChainTexture,
+    frame: Frame,
     encoder: wgpu::CommandEncoder,
 }
 
 pub struct Renderer {
-    surface: wgpu::Surface,
+    target: Target,
     device: wgpu::Device,
     queue: wgpu::Queue,
-    sc_desc: wgpu::SwapChainDescriptor,
-    swap_chain: wgpu::SwapChain,
+    format: wgpu::TextureFormat,
     size: winit::dpi::PhysicalSize<u32>,
+    antialiasing: Antialiasing,
+    multisampled_framebuffer: Option<wgpu::TextureView>,
     triangles_pipeline: wgpu::RenderPipeline,
     lines_pipeline: wgpu::RenderPipeline,
     pub triangles_buffer: DualBuffer,
     pub lines_buffer: DualBuffer,
+    pub text_sections: TextSections,
+    background: Background,
+    background_buffer: DualBuffer,
+    background_handle: Option<AllocationHandle>,
     staging_belt: wgpu::util::StagingBelt,
     glyph_brush: GlyphBrush<(), ab_glyph::FontArc>,
+    /// Whether text was queued with the glyph brush since the last frame.
+    text_queued: bool,
+    fonts: FontRegistry,
+    textures: Textures,
     local_pool: futures::executor::LocalPool,
     local_spawner: futures::executor::LocalSpawner,
     rip: Option<RenderInProgress>,
+    frame_stats: FrameStats,
 }
 
 impl Renderer {
     pub async fn new(window: &Window) -> Self {
+        Self::with_config(window, RendererConfig::default()).await
+    }
+
+    pub async fn with_config(window: &Window, config: RendererConfig) -> Self {
         let size = window.inner_size();
 
         let instance = wgpu::Instance::new(wgpu::BackendBit::PRIMARY);
@@ -94,7 +268,81 @@ impl Renderer {
             .expect("Failed to find an appropriate adapter");
 
         // Create the logical device and command queue.
-        let (device, queue) = adapter
+        let (device, queue) = Self::request_device(&adapter)
+            .await
+            .expect("Failed to create device");
+
+        let format = wgpu::TextureFormat::Bgra8UnormSrgb;
+
+        // Swapchain
+        let sc_desc = wgpu::SwapChainDescriptor {
+            usage: wgpu::TextureUsage::OUTPUT_ATTACHMENT,
+            format,
+            width: size.width,
+            height: size.height,
+            present_mode: wgpu::PresentMode::Mailbox,
+        };
+        let swap_chain = device.create_swap_chain(&surface, &sc_desc);
+
+        let target = Target::Window {
+            surface,
+            sc_desc,
+            swap_chain,
+        };
+
+        Self::build(device, queue, target, format, size, config)
+    }
+
+    /// Create a renderer that draws into an offscreen texture.
+    ///
+    /// A software adapter is preferred, since it draws the same pixels on
+    /// every machine. Otherwise the default adapter is used, or failing that
+    /// any other. Returns `None` if no adapter or device is available.
+    pub async fn new_headless(width: u32, height: u32) -> Option<Self> {
+        Self::new_headless_with_config(width, height, RendererConfig::default()).await
+    }
+
+    pub async fn new_headless_with_config(
+        width: u32,
+        height: u32,
+        config: RendererConfig,
+    ) -> Option<Self> {
+        let size = winit::dpi::PhysicalSize::new(width, height);
+
+        let instance = wgpu::Instance::new(wgpu::BackendBit::all());
+        let software = instance
+            .enumerate_adapters(wgpu::BackendBit::all())
+            .find(|adapter| adapter.get_info().device_type == wgpu::DeviceType::Cpu);
+        let adapter = match software {
+            Some(adapter) => adapter,
+            None => match instance
+                .request_adapter(&wgpu::RequestAdapterOptions {
+                    power_preference: wgpu::PowerPreference::default(),
+                    compatible_surface: None,
+                })
+                .await
+            {
+                Some(adapter) => adapter,
+                None => instance
+                    .enumerate_adapters(wgpu::BackendBit::all())
+                    .next()?,
+            },
+        };
+
+        let (device, queue) = Self::request_device(&adapter).await.ok()?;
+
+        // Rgba matches the pixel layout of `image::RgbaImage`.
+        let format = wgpu::TextureFormat::Rgba8UnormSrgb;
+        let (texture, view) = create_target_texture(&device, format, size);
+        let target = Target::Texture { texture, view };
+
+        Some(Self::build(device, queue, target, format, size, config))
+    }
+
+    async fn request_device(
+        adapter: &wgpu::Adapter,
+    ) -> Result<(wgpu::Device, wgpu::Queue), wgpu::RequestDeviceError> {
+        adapter
             .request_device(
                 &wgpu::DeviceDescriptor {
                     //label: None,
@@ -105,8 +353,17 @@ impl Renderer {
                 None,
             )
             .await
-            .expect("Failed to create device");
+    }
 
+    /// Create the resources shared by window and headless renderers.
+    fn build(
+        device: wgpu::Device,
+        queue: wgpu::Queue,
+        target: Target,
+        format: wgpu::TextureFormat,
+        size: winit::dpi::PhysicalSize<u32>,
+        config: RendererConfig,
+    ) -> Self {
         // Used for writing data to GPU buffers.
         let staging_belt = wgpu::util::StagingBelt::new(1024);
 
@@ -114,83 +371,134 @@ impl Renderer {
         let local_pool = futures::executor::LocalPool::new();
         let local_spawner = local_pool.spawner();
 
-        let format = wgpu::TextureFormat::Bgra8UnormSrgb;
-
         // Prepare glyph brush
-        let font = ab_glyph::FontArc::try_from_slice(include_bytes!("UbuntuMono-R.ttf"))
+        let font = FontRegistry::parse_static(include_bytes!("UbuntuMono-R.ttf"))
             .expect("Failed to load font.");
 
-        let glyph_brush =
-            GlyphBrushBuilder::using_font(font).build(&device, format);
+        let glyph_brush = GlyphBrushBuilder::using_font(font.clone()).build(&device, format);
+        let fonts = FontRegistry::new(font);
 
-        // Swapchain
-        let sc_desc = wgpu::SwapChainDescriptor {
-            usage: wgpu::TextureUsage::OUTPUT_ATTACHMENT,
-            format,
-            width: size.width,
-            height: size.height,
-            present_mode: wgpu::PresentMode::Mailbox,
-        };
-        let swap_chain = device.create_swap_chain(&surface, &sc_desc);
+        let textures = Textures::new(&device, &queue, config.atlas);
 
         // Load shaders
-        let vs_module = device.create_shader_module(wgpu::include_spirv!("shaders/shader.vert.spv"));
-        let fs_module = device.create_shader_module(wgpu::include_spirv!("shaders/shader.frag.spv"));
+        let vs_module =
+            device.create_shader_module(wgpu::include_spirv!("shaders/shader.vert.spv"));
+        let fs_module =
+            device.create_shader_module(wgpu::include_spirv!("shaders/shader.frag.spv"));
 
         // Create render pipelines
         let triangles_pipeline = create_pipeline(
             &device,
             &vs_module,
             &fs_module,
-            sc_desc.format,
+            format,
             wgpu::PrimitiveTopology::TriangleList,
+            config.antialiasing.sample_count(),
+            textures.bind_group_layout(),
         );
         let lines_pipeline = create_pipeline(
             &device,
             &vs_module,
             &fs_module,
-            sc_desc.format,
+            format,
             wgpu::PrimitiveTopology::LineList,
+            config.antialiasing.sample_count(),
+            textures.bind_group_layout(),
         );
 
         // Create buffers for render pipelines
         let triangles_buffer = DualBuffer::new(
-            &device,
             "Triangles",
-            TRIANGLES_MAX_VERTICES,
-            TRIANGLES_MAX_INDICES,
+            config.triangles_vertices,
+            config.triangles_indices,
         );
-        let lines_buffer = DualBuffer::new(&device, "Lines", LINES_MAX_VERTICES, LINES_MAX_INDICES);
-
+        let lines_buffer = DualBuffer::new("Lines", config.lines_vertices, config.lines_indices);
+
+        let background_buffer = DualBuffer::new("Background", 128, 256);
+
+        let multisampled_framebuffer = match config.antialiasing.sample_count() {
+            1 => None,
+            sample_count => Some(create_multisampled_framebuffer(
+                &device,
+                format,
+                size,
+                sample_count,
+            )),
+        };
 
         Self {
-            surface,
+            target,
             device,
             queue,
-            sc_desc,
-            swap_chain,
+            format,
             size,
+            antialiasing: config.antialiasing,
+            multisampled_framebuffer,
             triangles_pipeline,
             lines_pipeline,
             triangles_buffer,
             lines_buffer,
+            text_sections: TextSections::default(),
+            background: Background::default(),
+            background_buffer,
+            background_handle: None,
             staging_belt,
             glyph_brush,
+            text_queued: false,
+            fonts,
+            textures,
             local_pool,
             local_spawner,
             rip: None,
+            frame_stats: FrameStats::default(),
         }
     }
 
+    pub fn device(&self) -> &wgpu::Device {
+        &self.device
+    }
+
+    pub fn queue(&self) -> &wgpu::Queue {
+        &self.queue
+    }
+
+    /// Format of the frames, for pipelines passed to
+    /// [`Renderer::encode_underneath`].
+    pub fn format(&self) -> wgpu::TextureFormat {
+        self.format
+    }
+
     pub fn size(&self) -> winit::dpi::PhysicalSize<u32> {
         self.size
     }
 
     pub fn resize(&mut self, new_size: winit::dpi::PhysicalSize<u32>) {
         self.size = new_size;
-        self.sc_desc.width = new_size.width;
-        self.sc_desc.height = new_size.height;
-        self.swap_chain = self.device.create_swap_chain(&self.surface, &self.sc_desc);
+        match &mut self.target {
+            Target::Window {
+                surface,
+                sc_desc,
+                swap_chain,
+            } => {
+                sc_desc.width = new_size.width;
+                sc_desc.height = new_size.height;
+                *swap_chain = self.device.create_swap_chain(surface, sc_desc);
+            }
+            Target::Texture { texture, view } => {
+                let (new_texture, new_view) =
+                    create_target_texture(&self.device, self.format, new_size);
+                *texture = new_texture;
+                *view = new_view;
+            }
+        }
+        if self.multisampled_framebuffer.is_some() {
+            self.multisampled_framebuffer = Some(create_multisampled_framebuffer(
+                &self.device,
+                self.format,
+                new_size,
+                self.antialiasing.sample_count(),
+            ));
+        }
     }
 
     pub fn input(&mut self, _event: &WindowEvent) -> bool {
@@ -218,11 +526,15 @@ impl Renderer {
     }
 
     pub fn render_start(&mut self) {
-        let frame = self
-            .swap_chain
-            .get_current_frame()
-            .expect("Failed to get current swap chain frame.")
-            .output;
+        let frame = match &mut self.target {
+            Target::Window { swap_chain, .. } => Frame::SwapChain(
+                swap_chain
+                    .get_current_frame()
+                    .expect("Failed to get current swap chain frame.")
+                    .output,
+            ),
+            Target::Texture { .. } => Frame::Texture,
+        };
 
         let encoder = self
             .device
@@ -233,71 +545,125 @@ impl Renderer {
         self.rip = Some(RenderInProgress { frame, encoder });
     }
 
+    /// Record commands into the frame before the shapes are drawn, such as a
+    /// pass of the application's own that [`Background::NoClear`] keeps
+    /// underneath the shapes.
+    ///
+    /// Must be called between [`Renderer::render_start`] and
+    /// [`Renderer::render_finish`].
+    pub fn encode_underneath<F>(&mut self, encode: F)
+    where
+        F: FnOnce(&wgpu::Device, &mut wgpu::CommandEncoder, &wgpu::TextureView),
+    {
+        let rip = self
+            .rip
+            .as_mut()
+            .expect("Failed to encode outside of render_start and render_finish.");
+        encode(&self.device, &mut rip.encoder, rip.frame.view(&self.target));
+    }
+
     pub fn render_finish(&mut self) {
         if let Some(RenderInProgress { frame, mut encoder }) = self.rip.take() {
-            self.triangles_buffer
-                .write_buffer(&mut self.staging_belt, &mut encoder, &self.device);
-            self.lines_buffer
-                .write_buffer(&mut self.staging_belt, &mut encoder, &self.device);
-
+            let view = frame.view(&self.target);
+
+            let mut stats = FrameStats::default();
+            stats.uploaded_bytes += self.textures.take_uploaded_bytes();
+            stats.uploaded_bytes += self.background_buffer.write_buffer(
+                &mut self.staging_belt,
+                &mut encoder,
+                &self.device,
+            );
+            stats.uploaded_bytes += self.triangles_buffer.write_buffer(
+                &mut self.staging_belt,
+                &mut encoder,
+                &self.device,
+            );
+            stats.uploaded_bytes +=
+                self.lines_buffer
+                    .write_buffer(&mut self.staging_belt, &mut encoder, &self.device);
+
+            let load = match self.background.clear_color() {
+                Some(color) => wgpu::LoadOp::Clear(color),
+                None => wgpu::LoadOp::Load,
+            };
+
+            // Shapes are drawn into the multisampled framebuffer if there is
+            // one, which is resolved into the frame at the end of the pass.
+            let (attachment, resolve_target) = match &self.multisampled_framebuffer {
+                Some(framebuffer) => (framebuffer, Some(view)),
+                None => (view, None),
+            };
+
+            // Triangles and lines share one pass, switching pipelines in between.
             {
                 let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
-                    //label: None,
                     color_attachments: &[wgpu::RenderPassColorAttachmentDescriptor {
-                        attachment: &frame.view,
-                        resolve_target: None,
-                        ops: wgpu::Operations {
-                            load: wgpu::LoadOp::Clear(wgpu::Color {
-                                r: 0.1,
-                                g: 0.2,
-                                b: 0.3,
-                                a: 1.0,
-                            }),
-                            store: true,
-                        },
+                        attachment,
+                        resolve_target,
+                        ops: wgpu::Operations { load, store: true },
                     }],
                     depth_stencil_attachment: None,
                 });
-
-                render_pass.set_pipeline(&self.triangles_pipeline);
-
-                //if let Some(vertex_buffer, index_buffer) = self.dual_buffer.get_slice(0) {}
-                render_pass.set_vertex_buffer(0, self.triangles_buffer.vertex_buffer.slice(..));
-                render_pass.set_index_buffer(self.triangles_buffer.index_buffer.slice(..));
-                //render_pass.draw_indexed(0..self.num_indices, 0, 0..1);
-                render_pass.draw_indexed(0..self.triangles_buffer.indices_len(), 0, 0..1);
-                //render_pass.draw(0..3, 0..1);
+                stats.render_passes += 1;
+                render_pass.set_bind_group(0, self.textures.bind_group(), &[]);
+
+                if !self.background_buffer.is_empty() {
+                    render_pass.set_pipeline(&self.triangles_pipeline);
+                    render_pass
+                        .set_vertex_buffer(0, self.background_buffer.vertex_buffer().slice(..));
+                    render_pass.set_index_buffer(self.background_buffer.index_buffer().slice(..));
+                    for range in self.background_buffer.draw_ranges() {
+                        render_pass.draw_indexed(range.clone(), 0, 0..1);
+                        stats.draw_calls += 1;
+                    }
+                }
+
+                if !self.triangles_buffer.is_empty() {
+                    render_pass.set_pipeline(&self.triangles_pipeline);
+                    render_pass
+                        .set_vertex_buffer(0, self.triangles_buffer.vertex_buffer().slice(..));
+                    render_pass.set_index_buffer(self.triangles_buffer.index_buffer().slice(..));
+                    for range in self.triangles_buffer.draw_ranges() {
+                        render_pass.draw_indexed(range.clone(), 0, 0..1);
+                        stats.draw_calls += 1;
+                    }
+                }
+
+                if !self.lines_buffer.is_empty() {
+                    render_pass.set_pipeline(&self.lines_pipeline);
+                    render_pass.set_vertex_buffer(0, self.lines_buffer.vertex_buffer().slice(..));
+                    render_pass.set_index_buffer(self.lines_buffer.index_buffer().slice(..));
+                    for range in self.lines_buffer.draw_ranges() {
+                        render_pass.draw_indexed(range.clone(), 0, 0..1);
+                        stats.draw_calls += 1;
+                    }
+                }
             }
 
-            if !self.lines_buffer.is_empty() {
-                let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
-                    color_attachments: &[wgpu::RenderPassColorAttachmentDescriptor {
-                        attachment: &frame.view,
-                        resolve_target: None,
-                        ops: wgpu::Operations {
-                            load: wgpu::LoadOp::Load,
-                            store: true,
-                        },
-                    }],
-                    depth_stencil_attachment: None,
-                });
-
-                render_pass.set_pipeline(&self.lines_pipeline);
-                render_pass.set_vertex_buffer(0, self.lines_buffer.vertex_buffer.slice(..));
-                render_pass.set_index_buffer(self.lines_buffer.index_buffer.slice(..));
-                render_pass.draw_indexed(0..self.lines_buffer.indices_len(), 0, 0..1);
+            let (width, height) = (self.size.width as f32, self.size.height as f32);
+            for section in self.text_sections.sorted() {
+                self.glyph_brush
+                    .queue(section.section(&self.fonts, width, height));
+                self.text_queued = true;
             }
 
-            self.glyph_brush
-                .draw_queued(
-                    &self.device,
-                    &mut self.staging_belt,
-                    &mut encoder,
-                    &frame.view,
-                    self.size.width,
-                    self.size.height,
-                )
-                .expect("Draw queued");
+            // The glyph brush begins a pass of its own even with nothing
+            // queued, so it is only run when there is text.
+            if std::mem::take(&mut self.text_queued) {
+                self.glyph_brush
+                    .draw_queued(
+                        &self.device,
+                        &mut self.staging_belt,
+                        &mut encoder,
+                        view,
+                        self.size.width,
+                        self.size.height,
+                    )
+                    .expect("Draw queued");
+                stats.render_passes += 1;
+                stats.draw_calls += 1;
+            }
+            self.frame_stats = stats;
 
             self.staging_belt.finish();
             self.queue.submit(std::iter::once(encoder.finish()));
@@ -310,14 +676,271 @@ impl Renderer {
         }
     }
 
+    /// Render a frame and copy it into an image.
+    ///
+    /// Panics if the renderer wasn't created with [`Renderer::new_headless`].
+    pub fn render_to_image(&mut self) -> image::RgbaImage {
+        self.render_start();
+        self.render_finish();
+
+        let texture = match &self.target {
+            Target::Texture { texture, .. } => texture,
+            Target::Window { .. } => panic!("Only headless renderers can render to an image."),
+        };
+
+        // Rows in the copy buffer have to be aligned.
+        let width = self.size.width;
+        let height = self.size.height;
+        let unpadded_bytes_per_row = width * 4;
+        let align = wgpu::COPY_BYTES_PER_ROW_ALIGNMENT;
+        let padding = (align - unpadded_bytes_per_row % align) % align;
+        let padded_bytes_per_row = unpadded_bytes_per_row + padding;
+
+        let mut usage = wgpu::BufferUsage::MAP_READ;
+        usage.insert(wgpu::BufferUsage::COPY_DST);
+        let buffer = self.device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Readback Buffer"),
+            size: padded_bytes_per_row as u64 * height as u64,
+            usage,
+            mapped_at_creation: false,
+        });
+
+        let mut encoder = self
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                label: Some("Readback Encoder"),
+            });
+        encoder.copy_texture_to_buffer(
+            wgpu::TextureCopyView {
+                texture,
+                mip_level: 0,
+                origin: wgpu::Origin3d::ZERO,
+            },
+            wgpu::BufferCopyView {
+                buffer: &buffer,
+                layout: wgpu::TextureDataLayout {
+                    offset: 0,
+                    bytes_per_row: padded_bytes_per_row,
+                    rows_per_image: height,
+                },
+            },
+            wgpu::Extent3d {
+                width,
+                height,
+                depth: 1,
+            },
+        );
+        self.queue.submit(std::iter::once(encoder.finish()));
+
+        let slice = buffer.slice(..);
+        let mapping = slice.map_async(wgpu::MapMode::Read);
+        self.device.poll(wgpu::Maintain::Wait);
+        futures::executor::block_on(mapping).expect("Failed to map readback buffer.");
+
+        let mut pixels = Vec::with_capacity((unpadded_bytes_per_row * height) as usize);
+        {
+            let data = slice.get_mapped_range();
+            for row in data.chunks(padded_bytes_per_row as usize) {
+                pixels.extend_from_slice(&row[..unpadded_bytes_per_row as usize]);
+            }
+        }
+        buffer.unmap();
+
+        image::RgbaImage::from_raw(width, height, pixels).expect("Readback size mismatch.")
+    }
+
+    /// Clear each frame to a single colour.
+    pub fn set_clear_color(&mut self, color: Color) {
+        self.set_background(Background::Solid(color))
+            .expect("Failed to set solid background.");
+    }
+
+    pub fn background(&self) -> Background {
+        self.background
+    }
+
+    /// Change what is drawn underneath all shapes, starting with the next frame.
+    pub fn set_background(&mut self, background: Background) -> Result<(), BackgroundError> {
+        if background == Background::NoClear && self.antialiasing.sample_count() > 1 {
+            return Err(BackgroundError::NoClearWithMultisampling);
+        }
+
+        if let Some(handle) = self.background_handle.take() {
+            self.background_buffer
+                .free(handle)
+                .expect("Failed to free buffer allocation.");
+        }
+
+        if let Some((vertices, indices)) = background.mesh() {
+            let handle = self
+                .background_buffer
+                .alloc(vertices.len() as u32, indices.len() as u32)
+                .expect("Failed to allocate buffer space.");
+            self.background_buffer
+                .write_vertices_with_translation(handle, &vertices, Point3D::default())
+                .expect("Failed to write vertices.");
+            self.background_buffer
+                .write_indices(handle, &indices)
+                .expect("Failed to write indices.");
+            self.background_handle = Some(handle);
+        }
+
+        self.background = background;
+        Ok(())
+    }
+
+    pub fn antialiasing(&self) -> Antialiasing {
+        self.antialiasing
+    }
+
+    /// Size of one pixel in normalised device coordinates.
+    pub fn pixel_size(&self) -> Vector2 {
+        Vector2::new(
+            2.0 / self.size.width.max(1) as f32,
+            2.0 / self.size.height.max(1) as f32,
+        )
+    }
+
+    /// Statistics for the last finished frame.
+    pub fn frame_stats(&self) -> FrameStats {
+        self.frame_stats
+    }
+
+    /// Add an image to the texture atlas, so [`Image`](crate::Image) shapes
+    /// can draw it. All images share one texture, so drawing them doesn't
+    /// add passes or draw calls.
+    pub fn add_image(&mut self, image: &image::RgbaImage) -> Result<TextureId, TextureError> {
+        self.textures.add(&self.device, &self.queue, image)
+    }
+
+    /// Free the atlas space used by an image. [`Image`](crate::Image) shapes
+    /// drawing it show only their tint colour from their next draw on.
+    /// Returns whether the image was in the atlas.
+    pub fn remove_image(&mut self, id: TextureId) -> bool {
+        self.textures.remove(id)
+    }
+
+    /// Changes every time an image is removed from the atlas.
+    pub(crate) fn atlas_generation(&self) -> u32 {
+        self.textures.generation()
+    }
+
+    /// Load a PNG or JPEG image from bytes.
+    pub fn load_image(&mut self, bytes: &[u8]) -> Result<TextureId, TextureError> {
+        self.add_image(&image::load_from_memory(bytes)?.to_rgba8())
+    }
+
+    /// Load a PNG or JPEG image file.
+    pub fn load_image_file<P: AsRef<Path>>(&mut self, path: P) -> Result<TextureId, TextureError> {
+        self.add_image(&image::open(path)?.to_rgba8())
+    }
+
+    /// Size of an image in pixels.
+    pub fn image_size(&self, id: TextureId) -> Option<(u32, u32)> {
+        self.textures.size(id)
+    }
+
+    /// Where `source` in an image is in the texture atlas, or the whole
+    /// image, for setting the texture coordinates of vertices.
+    pub fn uv_rect(&self, id: TextureId, source: Option<TextureRect>) -> Option<UvRect> {
+        self.textures.uv_rect(id, source)
+    }
+
+    pub fn fonts(&self) -> &FontRegistry {
+        &self.fonts
+    }
+
+    /// Add a font to the glyph brush and the font registry.
+    pub fn add_font(&mut self, font: ab_glyph::FontArc) -> FontId {
+        self.glyph_brush.add_font(font.clone());
+        self.fonts.add(font)
+    }
+
+    /// Load a TTF or OTF font from bytes.
+    pub fn load_font(&mut self, bytes: Vec<u8>) -> Result<FontId, FontError> {
+        Ok(self.add_font(FontRegistry::parse(bytes)?))
+    }
+
+    /// Load a TTF or OTF font from a static byte slice, such as `include_bytes!`.
+    pub fn load_font_static(&mut self, bytes: &'static [u8]) -> Result<FontId, FontError> {
+        Ok(self.add_font(FontRegistry::parse_static(bytes)?))
+    }
+
+    /// Load a TTF or OTF font file.
+    pub fn load_font_file<P: AsRef<Path>>(&mut self, path: P) -> Result<FontId, FontError> {
+        Ok(self.add_font(FontRegistry::parse_file(path)?))
+    }
+
+    /// Fonts tried in order for glyphs missing from the requested font.
+    pub fn set_font_fallbacks(&mut self, fallbacks: Vec<FontId>) {
+        self.fonts.set_fallbacks(fallbacks);
+    }
+
+    /// Size in pixels of `text`, see [`FontRegistry::measure_text`].
+    pub fn measure_text(
+        &self,
+        text: &str,
+        font: FontId,
+        scale: f32,
+        max_width: Option<f32>,
+    ) -> Size {
+        self.fonts.measure_text(text, font, scale, max_width)
+    }
+
     pub fn draw_text(&mut self, text: &str, position: Point2, color: Color ,scale: f32) {
+        self.draw_text_with_font(text, position, color, scale, FontRegistry::DEFAULT);
+    }
+
+    pub fn draw_text_with_font(
+        &mut self,
+        text: &str,
+        position: Point2,
+        color: Color,
+        scale: f32,
+        font: FontId,
+    ) {
+        self.text_queued = true;
         self.glyph_brush.queue(Section {
             screen_position: (position.x, position.y),
             bounds: (self.size.width as f32, self.size.height as f32),
-            text: vec![Text::new(text)
-                .with_color([color.r, color.g, color.b, 1.0])
-                .with_scale(scale)],
+            text: self
+                .fonts
+                .runs(text, font)
+                .into_iter()
+                .map(|(run, font)| {
+                    Text::new(run)
+                        .with_color([color.r, color.g, color.b, color.a])
+                        .with_scale(scale)
+                        .with_font_id(font)
+                })
+                .collect(),
             ..Section::default()
         });
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Rectangle, Shape};
+
+    #[test]
+    fn headless_renderer_reads_back_pixels() {
+        // Build servers without any adapter can't run this.
+        let mut renderer = match futures::executor::block_on(Renderer::new_headless(8, 8)) {
+            Some(renderer) => renderer,
+            None => return,
+        };
+        renderer
+            .set_background(Background::Solid(Color::RED))
+            .unwrap();
+        let mut rectangle = Rectangle::new(&mut renderer, Vector2::new(0.5, 1.0));
+        rectangle.set_color(Color::BLUE);
+        rectangle.draw(&mut renderer, Point2::new(0.0, 0.0));
+
+        let image = renderer.render_to_image();
+        assert_eq!(image.dimensions(), (8, 8));
+        assert_eq!(image.get_pixel(1, 4).0, [0, 0, 255, 255]);
+        assert_eq!(image.get_pixel(6, 4).0, [255, 0, 0, 255]);
+    }
+}
@@ -1,9 +1,21 @@
 #version 440
 
-layout(location=0) in vec3 v_color;
+layout(location=0) in vec4 v_color;
+layout(location=1) in vec2 v_tex_coords;
+layout(location=2) flat in uint v_filter;
+layout(location=3) flat in uint v_page;
 layout(location=0) out vec4 f_color;
 
+layout(set=0, binding=0) uniform texture2DArray t_atlas;
+layout(set=0, binding=1) uniform sampler s_nearest;
+layout(set=0, binding=2) uniform sampler s_linear;
+
 void main() {
-    f_color = vec4(v_color, 1.0);
+    // Both are sampled so the texture lookups stay in uniform control flow.
+    vec3 coords = vec3(v_tex_coords, float(v_page));
+    vec4 nearest = texture(sampler2DArray(t_atlas, s_nearest), coords);
+    vec4 linear = texture(sampler2DArray(t_atlas, s_linear), coords);
+    vec4 color = v_color * (v_filter == 0u ? nearest : linear);
+    // Premultiply for the blend state set up in `create_pipeline`.
+    f_color = vec4(color.rgb * color.a, color.a);
 }
-
@@ -1,12 +1,21 @@
 #version 440
 
 layout(location=0) in vec3 a_position;
-layout(location=1) in vec3 a_color;
+layout(location=1) in vec4 a_color;
+layout(location=2) in vec2 a_tex_coords;
+layout(location=3) in uint a_filter;
+layout(location=4) in uint a_page;
 
-layout(location=0) out vec3 v_color;
+layout(location=0) out vec4 v_color;
+layout(location=1) out vec2 v_tex_coords;
+layout(location=2) flat out uint v_filter;
+layout(location=3) flat out uint v_page;
 
 void main() {
     v_color = a_color;
+    v_tex_coords = a_tex_coords;
+    v_filter = a_filter;
+    v_page = a_page;
     gl_Position = vec4(a_position, 1.0);
     //gl_Position.y = -gl_Position.y;
 }
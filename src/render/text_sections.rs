@@ -0,0 +1,228 @@
+use crate::{Color, FontRegistry, Point2, Vector2};
+use std::sync::atomic::{AtomicU32, Ordering};
+use wgpu_glyph::{FontId, HorizontalAlign, Layout, Section, Text, VerticalAlign};
+
+/// Text kept on the renderer and queued on the glyph brush every frame.
+///
+/// Positions and bounds use the same normalised coordinates as the other
+/// shapes. The glyph brush caches the layout of a section between frames as
+/// long as the section doesn't change.
+#[derive(Clone, Debug)]
+pub struct TextSection {
+    pub text: String,
+    /// Top left corner of the bounds.
+    pub position: Point2,
+    /// Width and height of the bounds. Zero or negative means unbounded.
+    pub bounds: Vector2,
+    pub color: Color,
+    /// Preferred font. Missing glyphs use the registry's fallbacks.
+    pub font: FontId,
+    /// Font size in pixels.
+    pub scale: f32,
+    pub h_align: HorizontalAlign,
+    pub v_align: VerticalAlign,
+    /// Wrap words at the bounds instead of keeping a single line.
+    pub wrap: bool,
+    /// Sections with a higher z index are drawn on top.
+    pub z_index: i32,
+}
+
+impl TextSection {
+    /// Build a glyph brush section in pixels for a target of the given size.
+    pub fn section<'a>(&'a self, fonts: &FontRegistry, width: f32, height: f32) -> Section<'a> {
+        let bound = |normalised: f32, pixels: f32| {
+            if normalised > 0.0 {
+                normalised * pixels
+            } else {
+                f32::INFINITY
+            }
+        };
+        let bounds = (bound(self.bounds.x, width), bound(self.bounds.y, height));
+
+        // The glyph brush aligns text around the screen position, so move it
+        // to the matching edge or centre of the bounds.
+        let x_offset = match self.h_align {
+            HorizontalAlign::Left => 0.0,
+            HorizontalAlign::Center => 0.5,
+            HorizontalAlign::Right => 1.0,
+        };
+        let y_offset = match self.v_align {
+            VerticalAlign::Top => 0.0,
+            VerticalAlign::Center => 0.5,
+            VerticalAlign::Bottom => 1.0,
+        };
+        let offset = |bound: f32, factor: f32| {
+            if bound.is_finite() {
+                bound * factor
+            } else {
+                0.0
+            }
+        };
+
+        let layout = if self.wrap {
+            Layout::default_wrap()
+        } else {
+            Layout::default_single_line()
+        };
+
+        Section {
+            screen_position: (
+                self.position.x * width + offset(bounds.0, x_offset),
+                self.position.y * height + offset(bounds.1, y_offset),
+            ),
+            bounds,
+            layout: layout.h_align(self.h_align).v_align(self.v_align),
+            text: fonts
+                .runs(&self.text, self.font)
+                .into_iter()
+                .map(|(run, font)| {
+                    Text::new(run)
+                        .with_color([self.color.r, self.color.g, self.color.b, self.color.a])
+                        .with_scale(self.scale)
+                        .with_font_id(font)
+                })
+                .collect(),
+        }
+    }
+}
+
+/// Source of unique ids so handles can't be used on the wrong sections.
+static NEXT_SECTIONS_ID: AtomicU32 = AtomicU32::new(0);
+
+/// Refers to a section inside a specific [`TextSections`].
+///
+/// The generation changes every time a slot is freed, so old handles to a
+/// reused slot are detected as stale.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct SectionHandle {
+    sections_id: u32,
+    slot: u32,
+    generation: u32,
+}
+
+/// A section slot and the generation of its current occupant.
+#[derive(Default)]
+struct Slot {
+    generation: u32,
+    section: Option<TextSection>,
+}
+
+/// Holds the retained text sections of a renderer.
+pub struct TextSections {
+    id: u32,
+    slots: Vec<Slot>,
+}
+
+impl Default for TextSections {
+    fn default() -> Self {
+        Self {
+            id: NEXT_SECTIONS_ID.fetch_add(1, Ordering::Relaxed),
+            slots: Vec::new(),
+        }
+    }
+}
+
+impl TextSections {
+    /// Store a section and return its handle.
+    pub fn alloc(&mut self, section: TextSection) -> SectionHandle {
+        let slot = match self.slots.iter().position(|slot| slot.section.is_none()) {
+            Some(slot) => slot,
+            None => {
+                self.slots.push(Slot::default());
+                self.slots.len() - 1
+            }
+        };
+        self.slots[slot].section = Some(section);
+        SectionHandle {
+            sections_id: self.id,
+            slot: slot as u32,
+            generation: self.slots[slot].generation,
+        }
+    }
+
+    /// Look up the slot a handle refers to, if it is still live.
+    fn slot(&mut self, handle: SectionHandle) -> Option<&mut Slot> {
+        if handle.sections_id != self.id {
+            return None;
+        }
+        self.slots
+            .get_mut(handle.slot as usize)
+            .filter(|slot| slot.generation == handle.generation && slot.section.is_some())
+    }
+
+    /// The section of a live handle, or `None` if it was freed or belongs to
+    /// other sections.
+    pub fn get_mut(&mut self, handle: SectionHandle) -> Option<&mut TextSection> {
+        self.slot(handle)?.section.as_mut()
+    }
+
+    /// Remove a section. Returns whether the handle was live.
+    pub fn free(&mut self, handle: SectionHandle) -> bool {
+        match self.slot(handle) {
+            Some(slot) => {
+                slot.section = None;
+                slot.generation = slot.generation.wrapping_add(1);
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &TextSection> {
+        self.slots.iter().filter_map(|slot| slot.section.as_ref())
+    }
+
+    /// Sections in drawing order, sorted by z index.
+    pub fn sorted(&self) -> Vec<&TextSection> {
+        let mut sections: Vec<_> = self.iter().collect();
+        sections.sort_by_key(|section| section.z_index);
+        sections
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn section(text: &str) -> TextSection {
+        TextSection {
+            text: text.to_string(),
+            position: Point2::new(0.0, 0.0),
+            bounds: Vector2::new(0.0, 0.0),
+            color: Color::WHITE,
+            font: FontRegistry::DEFAULT,
+            scale: 16.0,
+            h_align: HorizontalAlign::Left,
+            v_align: VerticalAlign::Top,
+            wrap: true,
+            z_index: 0,
+        }
+    }
+
+    #[test]
+    fn stale_handles_are_rejected() {
+        let mut sections = TextSections::default();
+        let a = sections.alloc(section("a"));
+        assert!(sections.free(a));
+        assert!(!sections.free(a));
+
+        // The slot is reused, but the old handle must not reach the new text.
+        let b = sections.alloc(section("b"));
+        assert_eq!(b.slot, a.slot);
+        assert!(sections.get_mut(a).is_none());
+        assert!(!sections.free(a));
+        assert_eq!(sections.get_mut(b).unwrap().text, "b");
+    }
+
+    #[test]
+    fn handles_from_other_sections_are_rejected() {
+        let mut sections = TextSections::default();
+        let mut other = TextSections::default();
+        sections.alloc(section("a"));
+        let b = other.alloc(section("b"));
+
+        assert!(sections.get_mut(b).is_none());
+        assert!(!sections.free(b));
+        assert_eq!(sections.iter().count(), 1);
+    }
+}
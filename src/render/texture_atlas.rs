@@ -0,0 +1,605 @@
+//! Packing images into the pages of a texture atlas, on the CPU.
+
+use super::textures::TextureError;
+use crate::{Vector2, Vertex};
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU32, Ordering};
+
+/// Source of unique ids so images can't be looked up in the wrong atlas.
+static NEXT_ATLAS_ID: AtomicU32 = AtomicU32::new(0);
+
+/// Identifies an image added to a [`TextureAtlas`] or a
+/// [`Renderer`](crate::Renderer).
+///
+/// Ids only refer to images in the atlas that made them.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct TextureId {
+    atlas_id: u32,
+    index: u32,
+}
+
+/// A rectangle of pixels, from the top left corner of an image or page.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TextureRect {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+/// Settings used when building a [`TextureAtlas`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct AtlasConfig {
+    /// Width and height of each page in pixels.
+    pub page_size: u32,
+    /// Empty pixels between neighbouring images.
+    pub padding: u32,
+    /// Pixels each image's edge is repeated outwards, so filtering at the
+    /// edge never picks up a neighbour.
+    pub extrusion: u32,
+    /// Most pages the atlas grows to.
+    pub max_pages: u32,
+}
+
+impl Default for AtlasConfig {
+    fn default() -> Self {
+        Self {
+            page_size: 1024,
+            padding: 1,
+            extrusion: 1,
+            max_pages: 16,
+        }
+    }
+}
+
+/// Where an image is in a [`TextureAtlas`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct AtlasEntry {
+    pub page: u32,
+    /// The image's pixels on the page, inside its extruded edge.
+    pub rect: TextureRect,
+}
+
+/// Part of an atlas page in texture coordinates, from 0 to 1 across the page.
+///
+/// The default is the white pixel every renderer keeps at the top left of its
+/// first page.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct UvRect {
+    pub page: u32,
+    pub top_left: Vector2,
+    pub bottom_right: Vector2,
+}
+
+impl Default for UvRect {
+    fn default() -> Self {
+        Self {
+            page: 0,
+            top_left: Vector2::new(0.0, 0.0),
+            bottom_right: Vector2::new(0.0, 0.0),
+        }
+    }
+}
+
+impl UvRect {
+    /// Texture coordinates of a point given as fractions across the rectangle,
+    /// from (0, 0) at the top left to (1, 1) at the bottom right.
+    pub fn tex_coords(&self, x: f32, y: f32) -> [f32; 2] {
+        let size = self.bottom_right - self.top_left;
+        [self.top_left.x + size.x * x, self.top_left.y + size.y * y]
+    }
+
+    /// Point `vertex` at a point given as fractions across the rectangle.
+    pub fn apply(&self, vertex: &mut Vertex, x: f32, y: f32) {
+        vertex.tex_coords = self.tex_coords(x, y);
+        vertex.page = self.page;
+    }
+}
+
+/// A free stretch of a shelf.
+#[derive(Clone, Copy, Debug)]
+struct Span {
+    x: u32,
+    width: u32,
+}
+
+/// A row of images, as tall as the space it was opened for.
+#[derive(Debug)]
+struct Shelf {
+    y: u32,
+    height: u32,
+    /// Free spans in order of `x`, never touching each other.
+    free: Vec<Span>,
+}
+
+impl Shelf {
+    fn is_empty(&self, page_size: u32) -> bool {
+        self.free.len() == 1 && self.free[0].width == page_size
+    }
+
+    fn span_for(&self, width: u32) -> Option<usize> {
+        self.free.iter().position(|span| span.width >= width)
+    }
+
+    /// Take `width` pixels from the start of a free span.
+    fn take(&mut self, span: usize, width: u32) -> u32 {
+        let x = self.free[span].x;
+        self.free[span].x += width;
+        self.free[span].width -= width;
+        if self.free[span].width == 0 {
+            self.free.remove(span);
+        }
+        x
+    }
+
+    /// Return a span, merging it with its free neighbours.
+    fn give_back(&mut self, x: u32, width: u32) {
+        let i = self.free.partition_point(|span| span.x < x);
+        self.free.insert(i, Span { x, width });
+        if i + 1 < self.free.len() && self.free[i].x + self.free[i].width == self.free[i + 1].x {
+            self.free[i].width += self.free[i + 1].width;
+            self.free.remove(i + 1);
+        }
+        if i > 0 && self.free[i - 1].x + self.free[i - 1].width == self.free[i].x {
+            self.free[i - 1].width += self.free[i].width;
+            self.free.remove(i);
+        }
+    }
+}
+
+/// Shelves stacked from the top of a page.
+#[derive(Debug, Default)]
+struct Page {
+    shelves: Vec<Shelf>,
+}
+
+impl Page {
+    fn bottom(&self) -> u32 {
+        self.shelves
+            .last()
+            .map_or(0, |shelf| shelf.y + shelf.height)
+    }
+
+    /// Find room for a slot, returning its top left corner.
+    fn alloc(&mut self, width: u32, height: u32, page_size: u32) -> Option<(u32, u32)> {
+        let best = self
+            .shelves
+            .iter()
+            .enumerate()
+            .filter(|(_, shelf)| shelf.height >= height && shelf.span_for(width).is_some())
+            .min_by_key(|(_, shelf)| shelf.height)
+            .map(|(i, _)| i);
+        let has_room = self.bottom() + height <= page_size;
+
+        // Shelves much taller than the slot waste space, so a new shelf is
+        // preferred while there's room for one.
+        let shelf = match best {
+            Some(i) if !has_room || self.shelves[i].height <= height * 2 => i,
+            _ if has_room => {
+                self.shelves.push(Shelf {
+                    y: self.bottom(),
+                    height,
+                    free: vec![Span {
+                        x: 0,
+                        width: page_size,
+                    }],
+                });
+                self.shelves.len() - 1
+            }
+            _ => return None,
+        };
+
+        let shelf = &mut self.shelves[shelf];
+        let span = shelf.span_for(width)?;
+        Some((shelf.take(span, width), shelf.y))
+    }
+
+    fn free(&mut self, x: u32, y: u32, width: u32, page_size: u32) {
+        if let Some(shelf) = self.shelves.iter_mut().find(|shelf| shelf.y == y) {
+            shelf.give_back(x, width);
+        }
+        // Empty shelves at the bottom give their height back to the page.
+        while self
+            .shelves
+            .last()
+            .is_some_and(|shelf| shelf.is_empty(page_size))
+        {
+            self.shelves.pop();
+        }
+    }
+}
+
+/// A slot on a page, holding an image with its extruded edge and padding.
+#[derive(Clone, Copy, Debug)]
+struct Slot {
+    entry: AtlasEntry,
+    x: u32,
+    y: u32,
+    width: u32,
+}
+
+/// Packs images into square pages with shelf packing.
+///
+/// Images are placed left to right on shelves as tall as the image they were
+/// opened for, and shelves are stacked top to bottom. Removing an image frees
+/// its space on the shelf for later images. When no page has room a new one
+/// is added, up to [`AtlasConfig::max_pages`].
+///
+/// Only positions are tracked here. Pixels are copied by the caller, with
+/// their edges extruded by [`extrude`].
+#[derive(Debug)]
+pub struct TextureAtlas {
+    id: u32,
+    config: AtlasConfig,
+    pages: Vec<Page>,
+    slots: HashMap<TextureId, Slot>,
+    next_id: u32,
+    /// Changes every time an image is removed.
+    generation: u32,
+}
+
+impl TextureAtlas {
+    pub fn new(config: AtlasConfig) -> Self {
+        Self {
+            id: NEXT_ATLAS_ID.fetch_add(1, Ordering::Relaxed),
+            config,
+            pages: Vec::new(),
+            slots: HashMap::new(),
+            next_id: 0,
+            generation: 0,
+        }
+    }
+
+    pub fn config(&self) -> AtlasConfig {
+        self.config
+    }
+
+    /// Changes every time an image is removed, so users of an image can tell
+    /// when to check that it is still there.
+    pub fn generation(&self) -> u32 {
+        self.generation
+    }
+
+    /// Number of pages in use, including pages that have become empty.
+    pub fn page_count(&self) -> u32 {
+        self.pages.len() as u32
+    }
+
+    /// Find room for an image. Ids are never reused.
+    pub fn add(&mut self, width: u32, height: u32) -> Result<TextureId, TextureError> {
+        let border = self.config.extrusion * 2 + self.config.padding;
+        let (slot_width, slot_height) = (width + border, height + border);
+        let page_size = self.config.page_size;
+        if slot_width > page_size || slot_height > page_size {
+            return Err(TextureError::TooLarge { width, height });
+        }
+
+        let found = self
+            .pages
+            .iter_mut()
+            .enumerate()
+            .find_map(|(i, page)| Some((i, page.alloc(slot_width, slot_height, page_size)?)));
+        let (page, (x, y)) = match found {
+            Some(found) => found,
+            None if self.page_count() < self.config.max_pages => {
+                let mut page = Page::default();
+                let corner = page
+                    .alloc(slot_width, slot_height, page_size)
+                    .ok_or(TextureError::TooLarge { width, height })?;
+                self.pages.push(page);
+                (self.pages.len() - 1, corner)
+            }
+            None => return Err(TextureError::AtlasFull),
+        };
+
+        let id = TextureId {
+            atlas_id: self.id,
+            index: self.next_id,
+        };
+        self.next_id += 1;
+        let extrusion = self.config.extrusion;
+        self.slots.insert(
+            id,
+            Slot {
+                entry: AtlasEntry {
+                    page: page as u32,
+                    rect: TextureRect {
+                        x: x + extrusion,
+                        y: y + extrusion,
+                        width,
+                        height,
+                    },
+                },
+                x,
+                y,
+                width: slot_width,
+            },
+        );
+        Ok(id)
+    }
+
+    /// Free the space used by an image. Returns whether it was in the atlas.
+    pub fn remove(&mut self, id: TextureId) -> bool {
+        let slot = match self.slots.remove(&id) {
+            Some(slot) => slot,
+            None => return false,
+        };
+        self.pages[slot.entry.page as usize].free(
+            slot.x,
+            slot.y,
+            slot.width,
+            self.config.page_size,
+        );
+        self.generation = self.generation.wrapping_add(1);
+        true
+    }
+
+    pub fn get(&self, id: TextureId) -> Option<AtlasEntry> {
+        self.slots.get(&id).map(|slot| slot.entry)
+    }
+
+    /// Texture coordinates of `source` in an image, or of the whole image.
+    /// Parts of `source` outside the image are cut off.
+    pub fn uv_rect(&self, id: TextureId, source: Option<TextureRect>) -> Option<UvRect> {
+        let AtlasEntry { page, rect } = self.get(id)?;
+        let source = source.unwrap_or(TextureRect {
+            x: 0,
+            y: 0,
+            width: rect.width,
+            height: rect.height,
+        });
+        let x = rect.x + source.x.min(rect.width);
+        let y = rect.y + source.y.min(rect.height);
+        let right = rect.x + source.x.saturating_add(source.width).min(rect.width);
+        let bottom = rect.y + source.y.saturating_add(source.height).min(rect.height);
+
+        let size = self.config.page_size as f32;
+        Some(UvRect {
+            page,
+            top_left: Vector2::new(x as f32 / size, y as f32 / size),
+            bottom_right: Vector2::new(right as f32 / size, bottom as f32 / size),
+        })
+    }
+}
+
+/// Copy `image` with its edge pixels repeated `extrusion` pixels outwards.
+pub fn extrude(image: &image::RgbaImage, extrusion: u32) -> image::RgbaImage {
+    let (width, height) = image.dimensions();
+    if width == 0 || height == 0 {
+        return image::RgbaImage::new(width + extrusion * 2, height + extrusion * 2);
+    }
+    image::RgbaImage::from_fn(width + extrusion * 2, height + extrusion * 2, |x, y| {
+        let x = x.saturating_sub(extrusion).min(width - 1);
+        let y = y.saturating_sub(extrusion).min(height - 1);
+        *image.get_pixel(x, y)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn atlas(page_size: u32, max_pages: u32) -> TextureAtlas {
+        TextureAtlas::new(AtlasConfig {
+            page_size,
+            padding: 1,
+            extrusion: 1,
+            max_pages,
+        })
+    }
+
+    /// The rectangle an image covers with its extruded edge and padding.
+    fn footprint(atlas: &TextureAtlas, id: TextureId) -> (u32, TextureRect) {
+        let AtlasEntry { page, rect } = atlas.get(id).unwrap();
+        let config = atlas.config();
+        (
+            page,
+            TextureRect {
+                x: rect.x - config.extrusion,
+                y: rect.y - config.extrusion,
+                width: rect.width + config.extrusion * 2 + config.padding,
+                height: rect.height + config.extrusion * 2 + config.padding,
+            },
+        )
+    }
+
+    fn overlap(a: TextureRect, b: TextureRect) -> bool {
+        a.x < b.x + b.width && b.x < a.x + a.width && a.y < b.y + b.height && b.y < a.y + a.height
+    }
+
+    fn assert_packed(atlas: &TextureAtlas, ids: &[TextureId]) {
+        let size = atlas.config().page_size;
+        for (i, &a) in ids.iter().enumerate() {
+            let (page_a, rect_a) = footprint(atlas, a);
+            assert!(rect_a.x + rect_a.width <= size && rect_a.y + rect_a.height <= size);
+            for &b in &ids[i + 1..] {
+                let (page_b, rect_b) = footprint(atlas, b);
+                assert!(
+                    page_a != page_b || !overlap(rect_a, rect_b),
+                    "{:?} overlaps {:?}",
+                    rect_a,
+                    rect_b
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn packs_without_overlap() {
+        let mut atlas = atlas(256, 1);
+        let ids: Vec<_> = (0..60)
+            .map(|i| atlas.add(5 + i * 7 % 23, 3 + i * 11 % 19).unwrap())
+            .collect();
+        assert_eq!(atlas.page_count(), 1);
+        assert_packed(&atlas, &ids);
+    }
+
+    #[test]
+    fn ids_from_other_atlases_are_rejected() {
+        let mut atlas_a = atlas(64, 1);
+        let mut atlas_b = atlas(64, 1);
+        let a = atlas_a.add(10, 10).unwrap();
+        let b = atlas_b.add(10, 10).unwrap();
+        assert_ne!(a, b);
+
+        assert!(atlas_a.get(b).is_none());
+        assert!(atlas_a.uv_rect(b, None).is_none());
+        assert!(!atlas_a.remove(b));
+        assert!(atlas_a.get(a).is_some());
+    }
+
+    #[test]
+    fn removing_changes_the_generation() {
+        let mut atlas = atlas(64, 1);
+        let a = atlas.add(10, 10).unwrap();
+        let generation = atlas.generation();
+        atlas.add(10, 10).unwrap();
+        assert_eq!(atlas.generation(), generation);
+
+        assert!(atlas.remove(a));
+        assert_ne!(atlas.generation(), generation);
+        let generation = atlas.generation();
+        assert!(!atlas.remove(a));
+        assert_eq!(atlas.generation(), generation);
+    }
+
+    #[test]
+    fn leaves_room_for_extrusion_and_padding() {
+        let mut atlas = atlas(64, 1);
+        let a = atlas.add(10, 10).unwrap();
+        let b = atlas.add(10, 10).unwrap();
+        assert_eq!(atlas.get(a).unwrap().rect.x, 1);
+        // 10 pixels, 2 extruded and 1 of padding, then b's extruded edge.
+        assert_eq!(atlas.get(b).unwrap().rect.x, 14);
+    }
+
+    #[test]
+    fn reuses_removed_space() {
+        let mut atlas = atlas(64, 1);
+        let ids: Vec<_> = (0..16).map(|_| atlas.add(13, 13).unwrap()).collect();
+        assert!(matches!(atlas.add(13, 13), Err(TextureError::AtlasFull)));
+
+        let removed = atlas.get(ids[5]).unwrap();
+        assert!(atlas.remove(ids[5]));
+        assert!(!atlas.remove(ids[5]));
+        assert_eq!(atlas.get(ids[5]), None);
+
+        let id = atlas.add(13, 13).unwrap();
+        assert_ne!(id, ids[5]);
+        assert_eq!(atlas.get(id), Some(removed));
+    }
+
+    #[test]
+    fn merges_freed_spans() {
+        let mut atlas = atlas(64, 1);
+        let ids: Vec<_> = (0..4).map(|_| atlas.add(13, 13).unwrap()).collect();
+        let _below = atlas.add(13, 13).unwrap();
+        atlas.remove(ids[1]);
+        atlas.remove(ids[2]);
+        let wide = atlas.add(29, 13).unwrap();
+        assert_eq!(
+            atlas.get(wide).unwrap().rect.y,
+            atlas.get(ids[0]).unwrap().rect.y
+        );
+    }
+
+    #[test]
+    fn reclaims_empty_shelves_at_the_bottom() {
+        let mut atlas = atlas(64, 1);
+        let top = atlas.add(60, 18).unwrap();
+        let bottom = atlas.add(60, 40).unwrap();
+        assert!(matches!(atlas.add(60, 40), Err(TextureError::AtlasFull)));
+
+        atlas.remove(bottom);
+        atlas.remove(top);
+        let tall = atlas.add(60, 60).unwrap();
+        assert_eq!(atlas.get(tall).unwrap().rect.y, 1);
+    }
+
+    #[test]
+    fn grows_into_new_pages() {
+        let mut atlas = atlas(64, 3);
+        let ids: Vec<_> = (0..3).map(|_| atlas.add(40, 40).unwrap()).collect();
+        let pages: Vec<_> = ids.iter().map(|&id| atlas.get(id).unwrap().page).collect();
+        assert_eq!(pages, vec![0, 1, 2]);
+        assert_eq!(atlas.page_count(), 3);
+        assert_packed(&atlas, &ids);
+        assert!(matches!(atlas.add(40, 40), Err(TextureError::AtlasFull)));
+
+        // Small images still fit beside the large ones.
+        let small = atlas.add(10, 10).unwrap();
+        assert_eq!(atlas.get(small).unwrap().page, 0);
+    }
+
+    #[test]
+    fn rejects_images_larger_than_a_page() {
+        let mut atlas = atlas(64, 4);
+        assert!(matches!(
+            atlas.add(62, 10),
+            Err(TextureError::TooLarge {
+                width: 62,
+                height: 10
+            })
+        ));
+        assert!(atlas.add(61, 61).is_ok());
+    }
+
+    #[test]
+    fn uv_rects_cover_sources() {
+        let mut atlas = atlas(64, 1);
+        let id = atlas.add(32, 16).unwrap();
+        let rect = atlas.get(id).unwrap().rect;
+
+        let whole = atlas.uv_rect(id, None).unwrap();
+        assert_eq!(whole.page, 0);
+        assert_eq!(
+            whole.top_left,
+            Vector2::new(rect.x as f32 / 64.0, rect.y as f32 / 64.0)
+        );
+        assert_eq!(
+            whole.bottom_right,
+            Vector2::new((rect.x + 32) as f32 / 64.0, (rect.y + 16) as f32 / 64.0)
+        );
+
+        // A source reaching past the image is cut off at its edge.
+        let source = TextureRect {
+            x: 16,
+            y: 8,
+            width: 32,
+            height: 32,
+        };
+        let part = atlas.uv_rect(id, Some(source)).unwrap();
+        assert_eq!(
+            part.top_left,
+            Vector2::new((rect.x + 16) as f32 / 64.0, (rect.y + 8) as f32 / 64.0)
+        );
+        assert_eq!(part.bottom_right, whole.bottom_right);
+        assert_eq!(
+            part.tex_coords(1.0, 1.0),
+            [whole.bottom_right.x, whole.bottom_right.y]
+        );
+    }
+
+    #[test]
+    fn extrudes_edges() {
+        let mut image = image::RgbaImage::new(2, 2);
+        image.put_pixel(0, 0, image::Rgba([1, 0, 0, 255]));
+        image.put_pixel(1, 0, image::Rgba([2, 0, 0, 255]));
+        image.put_pixel(0, 1, image::Rgba([3, 0, 0, 255]));
+        image.put_pixel(1, 1, image::Rgba([4, 0, 0, 255]));
+
+        let extruded = extrude(&image, 2);
+        assert_eq!(extruded.dimensions(), (6, 6));
+        let red = |x, y| extruded.get_pixel(x, y)[0];
+        assert_eq!(
+            (0..6).map(|x| red(x, 0)).collect::<Vec<_>>(),
+            vec![1, 1, 1, 2, 2, 2]
+        );
+        assert_eq!(
+            (0..6).map(|y| red(5, y)).collect::<Vec<_>>(),
+            vec![2, 2, 2, 4, 4, 4]
+        );
+        assert_eq!(red(2, 2), 1);
+        assert_eq!(red(3, 3), 4);
+    }
+}
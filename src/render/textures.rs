@@ -0,0 +1,321 @@
+use super::texture_atlas::{self, AtlasConfig, TextureAtlas, TextureId, TextureRect, UvRect};
+use std::fmt;
+
+/// How a texture is sampled between its pixels.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Filter {
+    /// Sharp pixels, for pixel art.
+    Nearest,
+    /// Smooth blending between pixels.
+    #[default]
+    Linear,
+}
+
+impl Filter {
+    /// Value of [`Vertex::filter`](crate::Vertex::filter) that selects this filter.
+    pub fn to_vertex(self) -> u32 {
+        match self {
+            Filter::Nearest => 0,
+            Filter::Linear => 1,
+        }
+    }
+}
+
+/// Errors returned when adding an image.
+#[derive(Debug)]
+pub enum TextureError {
+    Image(image::ImageError),
+    /// The image is bigger than a texture atlas page.
+    TooLarge {
+        width: u32,
+        height: u32,
+    },
+    /// Every texture atlas page is full.
+    AtlasFull,
+}
+
+impl fmt::Display for TextureError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TextureError::Image(err) => write!(f, "failed to load image: {}", err),
+            TextureError::TooLarge { width, height } => write!(
+                f,
+                "image of {}x{} pixels doesn't fit on a texture atlas page",
+                width, height
+            ),
+            TextureError::AtlasFull => write!(f, "texture atlas is full"),
+        }
+    }
+}
+
+impl std::error::Error for TextureError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            TextureError::Image(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<image::ImageError> for TextureError {
+    fn from(err: image::ImageError) -> Self {
+        TextureError::Image(err)
+    }
+}
+
+/// Images packed into the pages of one array texture, which every shape
+/// samples so textured and untextured shapes are drawn together.
+///
+/// The top left pixel of the first page is white. Untextured vertices sample
+/// it, so only their colour shows.
+pub(crate) struct Textures {
+    atlas: TextureAtlas,
+    texture: wgpu::Texture,
+    /// Pages the texture has layers for.
+    layers: u32,
+    nearest_sampler: wgpu::Sampler,
+    linear_sampler: wgpu::Sampler,
+    bind_group_layout: wgpu::BindGroupLayout,
+    bind_group: wgpu::BindGroup,
+    /// Bytes written to the texture since the last frame.
+    uploaded_bytes: u64,
+}
+
+/// Create the texture holding the atlas pages, one per array layer.
+fn create_texture(device: &wgpu::Device, page_size: u32, layers: u32) -> wgpu::Texture {
+    let mut usage = wgpu::TextureUsage::SAMPLED;
+    usage.insert(wgpu::TextureUsage::COPY_DST);
+    usage.insert(wgpu::TextureUsage::COPY_SRC);
+    device.create_texture(&wgpu::TextureDescriptor {
+        label: Some("Texture Atlas"),
+        size: wgpu::Extent3d {
+            width: page_size,
+            height: page_size,
+            depth: layers,
+        },
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: wgpu::TextureDimension::D2,
+        format: wgpu::TextureFormat::Rgba8UnormSrgb,
+        usage,
+    })
+}
+
+impl Textures {
+    pub fn new(device: &wgpu::Device, queue: &wgpu::Queue, config: AtlasConfig) -> Self {
+        let texture = create_texture(device, config.page_size, 1);
+
+        let sampler = |label, filter| {
+            device.create_sampler(&wgpu::SamplerDescriptor {
+                label: Some(label),
+                address_mode_u: wgpu::AddressMode::ClampToEdge,
+                address_mode_v: wgpu::AddressMode::ClampToEdge,
+                address_mode_w: wgpu::AddressMode::ClampToEdge,
+                mag_filter: filter,
+                min_filter: filter,
+                ..wgpu::SamplerDescriptor::default()
+            })
+        };
+        let nearest_sampler = sampler("Texture Atlas Nearest Sampler", wgpu::FilterMode::Nearest);
+        let linear_sampler = sampler("Texture Atlas Linear Sampler", wgpu::FilterMode::Linear);
+
+        let sampler_entry = |binding| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStage::FRAGMENT,
+            ty: wgpu::BindingType::Sampler { comparison: false },
+            count: None,
+        };
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Texture Atlas Bind Group Layout"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStage::FRAGMENT,
+                    ty: wgpu::BindingType::SampledTexture {
+                        dimension: wgpu::TextureViewDimension::D2Array,
+                        component_type: wgpu::TextureComponentType::Float,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+                sampler_entry(1),
+                sampler_entry(2),
+            ],
+        });
+        let bind_group = Self::create_bind_group(
+            device,
+            &bind_group_layout,
+            &texture,
+            &nearest_sampler,
+            &linear_sampler,
+        );
+
+        let mut textures = Self {
+            atlas: TextureAtlas::new(config),
+            texture,
+            layers: 1,
+            nearest_sampler,
+            linear_sampler,
+            bind_group_layout,
+            bind_group,
+            uploaded_bytes: 0,
+        };
+        let white = image::RgbaImage::from_pixel(1, 1, image::Rgba([255, 255, 255, 255]));
+        textures
+            .add(device, queue, &white)
+            .expect("Failed to add white pixel to texture atlas.");
+        textures
+    }
+
+    fn create_bind_group(
+        device: &wgpu::Device,
+        layout: &wgpu::BindGroupLayout,
+        texture: &wgpu::Texture,
+        nearest_sampler: &wgpu::Sampler,
+        linear_sampler: &wgpu::Sampler,
+    ) -> wgpu::BindGroup {
+        let view = texture.create_view(&wgpu::TextureViewDescriptor {
+            dimension: Some(wgpu::TextureViewDimension::D2Array),
+            ..wgpu::TextureViewDescriptor::default()
+        });
+        device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Texture Atlas Bind Group"),
+            layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Sampler(nearest_sampler),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: wgpu::BindingResource::Sampler(linear_sampler),
+                },
+            ],
+        })
+    }
+
+    pub fn bind_group_layout(&self) -> &wgpu::BindGroupLayout {
+        &self.bind_group_layout
+    }
+
+    pub fn bind_group(&self) -> &wgpu::BindGroup {
+        &self.bind_group
+    }
+
+    /// Bytes written to the texture since the last call.
+    pub fn take_uploaded_bytes(&mut self) -> u64 {
+        std::mem::take(&mut self.uploaded_bytes)
+    }
+
+    /// Copy `image` into free space in the atlas, adding a page if needed.
+    pub fn add(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        image: &image::RgbaImage,
+    ) -> Result<TextureId, TextureError> {
+        let (width, height) = image.dimensions();
+        let id = self.atlas.add(width, height)?;
+        if self.atlas.page_count() > self.layers {
+            self.grow(device, queue);
+        }
+        if width == 0 || height == 0 {
+            return Ok(id);
+        }
+
+        let entry = self
+            .atlas
+            .get(id)
+            .expect("Failed to find image in texture atlas.");
+        let extrusion = self.atlas.config().extrusion;
+        let extruded = texture_atlas::extrude(image, extrusion);
+        queue.write_texture(
+            wgpu::TextureCopyView {
+                texture: &self.texture,
+                mip_level: 0,
+                origin: wgpu::Origin3d {
+                    x: entry.rect.x - extrusion,
+                    y: entry.rect.y - extrusion,
+                    z: entry.page,
+                },
+            },
+            extruded.as_raw(),
+            wgpu::TextureDataLayout {
+                offset: 0,
+                bytes_per_row: extruded.width() * 4,
+                rows_per_image: extruded.height(),
+            },
+            wgpu::Extent3d {
+                width: extruded.width(),
+                height: extruded.height(),
+                depth: 1,
+            },
+        );
+        self.uploaded_bytes += extruded.as_raw().len() as u64;
+        Ok(id)
+    }
+
+    /// Free the space used by an image. Returns whether it was in the atlas.
+    pub fn remove(&mut self, id: TextureId) -> bool {
+        self.atlas.remove(id)
+    }
+
+    /// Changes every time an image is removed.
+    pub fn generation(&self) -> u32 {
+        self.atlas.generation()
+    }
+
+    /// Move the pages into a texture with a layer for every page, and bind
+    /// that instead.
+    fn grow(&mut self, device: &wgpu::Device, queue: &wgpu::Queue) {
+        let page_size = self.atlas.config().page_size;
+        let layers = self.atlas.page_count();
+        let texture = create_texture(device, page_size, layers);
+
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("Texture Atlas Encoder"),
+        });
+        encoder.copy_texture_to_texture(
+            wgpu::TextureCopyView {
+                texture: &self.texture,
+                mip_level: 0,
+                origin: wgpu::Origin3d::ZERO,
+            },
+            wgpu::TextureCopyView {
+                texture: &texture,
+                mip_level: 0,
+                origin: wgpu::Origin3d::ZERO,
+            },
+            wgpu::Extent3d {
+                width: page_size,
+                height: page_size,
+                depth: self.layers,
+            },
+        );
+        queue.submit(std::iter::once(encoder.finish()));
+
+        self.bind_group = Self::create_bind_group(
+            device,
+            &self.bind_group_layout,
+            &texture,
+            &self.nearest_sampler,
+            &self.linear_sampler,
+        );
+        self.texture = texture;
+        self.layers = layers;
+    }
+
+    /// Size of an image in pixels.
+    pub fn size(&self, id: TextureId) -> Option<(u32, u32)> {
+        let rect = self.atlas.get(id)?.rect;
+        Some((rect.width, rect.height))
+    }
+
+    pub fn uv_rect(&self, id: TextureId, source: Option<TextureRect>) -> Option<UvRect> {
+        self.atlas.uv_rect(id, source)
+    }
+}
@@ -1,11 +1,29 @@
+mod ellipse;
+pub use ellipse::{Arc, Circle, Ellipse, Sector};
+mod feather;
+mod fill;
+pub use fill::Fill;
+mod free_polygon;
+pub use free_polygon::FreePolygon;
+mod image;
+pub use self::image::Image;
 mod lines;
 pub use lines::Lines;
+mod mesh;
+mod path;
+pub use path::{Path, PathBuilder};
 mod polygon;
 pub use polygon::Polygon;
+mod polyline;
+pub use polyline::Polyline;
 mod rectangle;
-pub use rectangle::Rectangle;
+pub use rectangle::{CornerRadii, Rectangle};
+mod stroke;
+pub use stroke::{LineCap, LineJoin, LineWidth, StrokeStyle};
 mod text;
-pub use text::Text;
+pub use text::{HorizontalAlign, Text, VerticalAlign};
+mod triangulate;
+pub use triangulate::PolygonError;
 
 use crate::{Color, Point2, Renderer, Vector2};
 use std::ops::{Div, Mul};
@@ -67,9 +85,31 @@ impl From<winit::dpi::PhysicalSize<u32>> for Size {
     }
 }
 
+/// Which parts of a closed shape are drawn.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum DrawMode {
+    #[default]
+    Fill,
+    Stroke,
+    FillAndStroke,
+}
+
+impl DrawMode {
+    pub fn fills(self) -> bool {
+        self != DrawMode::Stroke
+    }
+
+    pub fn strokes(self) -> bool {
+        self != DrawMode::Fill
+    }
+}
+
 pub trait Shape {
     fn set_position(&mut self, position: Vector2);
     fn resize(&mut self, size: Vector2);
     fn set_color(&mut self, color: Color);
+    /// Layer of the shape. Higher layers are drawn on top of lower ones in
+    /// the same buffer. Shapes on the same layer keep their buffer order.
+    fn set_z_index(&mut self, z_index: i32);
     fn draw(&mut self, renderer: &mut Renderer, parent_pos: Point2);
 }
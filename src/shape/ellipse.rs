@@ -0,0 +1,544 @@
+use super::fill::{self, Fill};
+use super::mesh::{arc_segments, Mesh, MeshAllocation};
+use super::{feather, stroke};
+use crate::{
+    Antialiasing, Color, DrawMode, DualBuffer, LineWidth, Point2, Point3D, Renderer, Shape,
+    StrokeStyle, Vector2,
+};
+use cgmath::ElementWise;
+use std::f32::consts::PI;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Kind {
+    Ellipse,
+    Sector,
+    Arc,
+}
+
+/// Geometry shared by the round shapes.
+///
+/// Angles are in radians from the right and turn clockwise on screen. Curves
+/// are split with a fixed step for the current radius in pixels, and the
+/// buffer space is sized for a full turn, so changing the angles never
+/// reallocates it.
+#[derive(Debug)]
+struct Round {
+    kind: Kind,
+    /// Whether to keep the shape round, centred in its box.
+    circular: bool,
+    position: Vector2,
+    size: Vector2,
+    color: Color,
+    fill: Option<Fill>,
+    start_angle: f32,
+    end_angle: f32,
+    draw_mode: DrawMode,
+    stroke_width: LineWidth,
+    stroke_color: Color,
+    stroke_style: StrokeStyle,
+    feathered: bool,
+    /// Pixel size the mesh was built for.
+    pixel_size: Vector2,
+    mesh: Mesh,
+    allocation: MeshAllocation,
+    /// Whether the buffer space has to be sized again, which is needed for
+    /// every change except to the angles.
+    reserve: bool,
+    z_index: i32,
+    dirty: bool,
+}
+
+impl Round {
+    fn new(renderer: &mut Renderer, kind: Kind, circular: bool, size: Vector2) -> Self {
+        let feathered = renderer.antialiasing() == Antialiasing::Feathered;
+        Self::with_pixel_size(kind, circular, size, renderer.pixel_size(), feathered)
+    }
+
+    fn with_pixel_size(
+        kind: Kind,
+        circular: bool,
+        size: Vector2,
+        pixel_size: Vector2,
+        feathered: bool,
+    ) -> Self {
+        Self {
+            kind,
+            circular,
+            position: Vector2::new(0.0, 0.0),
+            size,
+            color: Color::default(),
+            fill: None,
+            start_angle: 0.0,
+            end_angle: 2.0 * PI,
+            draw_mode: DrawMode::default(),
+            stroke_width: LineWidth::Pixels(1.0),
+            stroke_color: Color::BLACK,
+            stroke_style: StrokeStyle::default(),
+            feathered,
+            pixel_size,
+            mesh: Mesh::default(),
+            allocation: MeshAllocation::default(),
+            reserve: true,
+            z_index: 0,
+            dirty: true,
+        }
+    }
+
+    fn free(mut self, renderer: &mut Renderer) {
+        self.allocation.free(&mut renderer.triangles_buffer);
+    }
+
+    fn set_angles(&mut self, start_angle: f32, end_angle: f32) {
+        self.start_angle = start_angle;
+        self.end_angle = end_angle;
+        self.dirty = true;
+    }
+
+    fn set_fill(&mut self, fill: Fill) {
+        self.fill = Some(fill);
+        self.reserve = true;
+        self.dirty = true;
+    }
+
+    fn set_draw_mode(&mut self, draw_mode: DrawMode) {
+        self.draw_mode = draw_mode;
+        self.reserve = true;
+        self.dirty = true;
+    }
+
+    fn set_stroke(&mut self, width: LineWidth, color: Color) {
+        self.stroke_width = width;
+        self.stroke_color = color;
+        self.reserve = true;
+        self.dirty = true;
+    }
+
+    fn set_stroke_style(&mut self, style: StrokeStyle) {
+        self.stroke_style = style;
+        self.reserve = true;
+        self.dirty = true;
+    }
+
+    /// Centre and radii in pixels, relative to the top left of the box.
+    fn ellipse(&self) -> (Vector2, Vector2) {
+        let size = Vector2::new(self.size.x.abs(), self.size.y.abs())
+            .div_element_wise(self.pixel_size)
+            * 2.0;
+        let center = Vector2::new(size.x, -size.y) / 2.0;
+        let radii = if self.circular {
+            let radius = size.x.min(size.y) / 2.0;
+            Vector2::new(radius, radius)
+        } else {
+            size / 2.0
+        };
+        (center, radii)
+    }
+
+    /// Angle between points, the same for any sweep.
+    fn step(&self) -> f32 {
+        let (_, radii) = self.ellipse();
+        2.0 * PI / arc_segments(radii.x.max(radii.y), 2.0 * PI) as f32
+    }
+
+    /// Most vertices and indices the stroke takes for any angles.
+    fn max_stroke_size(&self) -> (u32, u32) {
+        if self.kind != Kind::Arc && !self.draw_mode.strokes() {
+            return (0, 0);
+        }
+        let (_, radii) = self.ellipse();
+        let radius = radii.x.max(radii.y);
+        let segments = (2.0 * PI / self.step()).ceil() as usize;
+        // The arc and both radii of a sector.
+        let length = (2.0 * PI + 2.0) * radius;
+        stroke::max_size(
+            segments + 2,
+            self.kind == Kind::Sector,
+            length,
+            self.stroke_width.to_pixels(self.pixel_size),
+            &self.stroke_style,
+        )
+    }
+
+    /// Build the mesh for the angles from `start_angle` to `end_angle`.
+    fn build(&mut self, start_angle: f32, end_angle: f32) {
+        let (center, radii) = self.ellipse();
+        let sweep = (end_angle - start_angle).clamp(-2.0 * PI, 2.0 * PI);
+        let full = self.kind == Kind::Ellipse || sweep.abs() >= 2.0 * PI;
+        let segments = ((sweep.abs() / self.step()).ceil() as u32).max(1);
+        let point =
+            |angle: f32| center + Vector2::new(radii.x * angle.cos(), -radii.y * angle.sin());
+
+        self.mesh.clear();
+        // A full turn repeats its first point, so leave the last one out.
+        let count = if full { segments } else { segments + 1 };
+        let mut points: Vec<Vector2> = (0..count)
+            .map(|i| point(start_angle + sweep * i as f32 / segments as f32))
+            .collect();
+
+        if self.kind == Kind::Arc {
+            let points: Vec<_> = points.iter().map(|&point| (point, self.color)).collect();
+            stroke::stroke_with_style(
+                &mut self.mesh,
+                &points,
+                full,
+                self.stroke_width.to_pixels(self.pixel_size),
+                &self.stroke_style,
+            );
+            self.mesh.scale_from(0, self.pixel_size);
+            return;
+        }
+
+        if !full {
+            points.insert(0, center);
+        }
+        let outline: Vec<u32> = points
+            .iter()
+            .map(|&point| self.mesh.push_vertex(point, self.color))
+            .collect();
+        self.mesh.scale_from(0, self.pixel_size);
+
+        if self.draw_mode.fills() {
+            // Sectors fan out from the centre, which can see all of the edge.
+            for i in 1..(outline.len() - 1) {
+                self.mesh
+                    .push_triangle(outline[0], outline[i], outline[i + 1]);
+            }
+            // The fringe follows the edges as the fill split them.
+            let fringe = match &self.fill {
+                Some(fill) => fill::apply(&mut self.mesh, 0, fill).outline(&outline),
+                None => outline.clone(),
+            };
+            if self.feathered {
+                let first = self.mesh.vertices.len() as u32;
+                feather::push_indices(&mut self.mesh.indices, &fringe, first);
+                feather::push_vertices(&mut self.mesh.vertices, &fringe, self.pixel_size);
+            }
+        }
+        if self.draw_mode.strokes() {
+            stroke::stroke_outline(
+                &mut self.mesh,
+                &outline,
+                self.stroke_color,
+                self.stroke_width,
+                &self.stroke_style,
+                self.pixel_size,
+            );
+        }
+    }
+
+    /// Build the mesh for the current angles and copy it into `buffer`,
+    /// sizing the buffer space for any angles first if needed.
+    fn write(&mut self, buffer: &mut DualBuffer, translation: Point3D) {
+        if self.reserve && self.kind != Kind::Ellipse {
+            self.reserve = false;
+            // A turn just short of a full one has the most points to fill,
+            // but a shorter stroke can have more caps, joins or dashes, so
+            // add the most any stroke can take.
+            let end_angle = self.start_angle + 2.0 * PI - self.step() / 2.0;
+            self.build(self.start_angle, end_angle);
+            let (stroke_vertices, stroke_indices) = self.max_stroke_size();
+            self.allocation.reserve(
+                buffer,
+                self.mesh.vertices.len() as u32 + stroke_vertices,
+                self.mesh.indices.len() as u32 + stroke_indices,
+            );
+        }
+        self.build(self.start_angle, self.end_angle);
+
+        self.allocation
+            .write(buffer, &self.mesh, self.z_index, translation);
+    }
+}
+
+impl Shape for Round {
+    fn set_position(&mut self, position: Vector2) {
+        self.position = position;
+        self.dirty = true;
+    }
+
+    fn resize(&mut self, size: Vector2) {
+        self.size = size;
+        self.reserve = true;
+        self.dirty = true;
+    }
+
+    fn set_color(&mut self, color: Color) {
+        self.color = color;
+        self.fill = None;
+        self.dirty = true;
+    }
+
+    fn set_z_index(&mut self, z_index: i32) {
+        self.z_index = z_index;
+        self.dirty = true;
+    }
+
+    fn draw(&mut self, renderer: &mut Renderer, parent_pos: Point2) {
+        // Curves are split for the window size, so they have to be split
+        // again when it changes.
+        if self.pixel_size != renderer.pixel_size() {
+            self.pixel_size = renderer.pixel_size();
+            self.reserve = true;
+            self.dirty = true;
+        }
+        if !self.dirty {
+            return;
+        }
+        self.dirty = false;
+
+        self.write(
+            &mut renderer.triangles_buffer,
+            Point3D::from(parent_pos + self.position),
+        );
+    }
+}
+
+/// A circle as large as fits in the middle of its box.
+#[derive(Debug)]
+pub struct Circle(Round);
+
+impl Circle {
+    pub fn new(renderer: &mut Renderer, size: Vector2) -> Self {
+        Self(Round::new(renderer, Kind::Ellipse, true, size))
+    }
+}
+
+/// An ellipse filling its box.
+#[derive(Debug)]
+pub struct Ellipse(Round);
+
+impl Ellipse {
+    pub fn new(renderer: &mut Renderer, size: Vector2) -> Self {
+        Self(Round::new(renderer, Kind::Ellipse, false, size))
+    }
+}
+
+/// A pie slice of a circle as large as fits in the middle of its box.
+///
+/// Angles are in radians from the right and turn clockwise on screen.
+#[derive(Debug)]
+pub struct Sector(Round);
+
+impl Sector {
+    pub fn new(renderer: &mut Renderer, size: Vector2, start_angle: f32, end_angle: f32) -> Self {
+        let mut round = Round::new(renderer, Kind::Sector, true, size);
+        round.set_angles(start_angle, end_angle);
+        Self(round)
+    }
+
+    /// Change the angles without reallocating buffer space, for animation.
+    pub fn set_angles(&mut self, start_angle: f32, end_angle: f32) {
+        self.0.set_angles(start_angle, end_angle);
+    }
+}
+
+/// A curved line along part of a circle as large as fits in the middle of its
+/// box.
+///
+/// Angles are in radians from the right and turn clockwise on screen. The
+/// line is centred on the circle and has butt caps unless its stroke style
+/// says otherwise. It is drawn in the colour set with [`Shape::set_color`].
+#[derive(Debug)]
+pub struct Arc(Round);
+
+impl Arc {
+    pub fn new(renderer: &mut Renderer, size: Vector2, start_angle: f32, end_angle: f32) -> Self {
+        let mut round = Round::new(renderer, Kind::Arc, true, size);
+        round.set_angles(start_angle, end_angle);
+        Self(round)
+    }
+
+    /// Release the buffer space used by this shape.
+    pub fn free(self, renderer: &mut Renderer) {
+        self.0.free(renderer);
+    }
+
+    /// Change the angles without reallocating buffer space, for animation.
+    pub fn set_angles(&mut self, start_angle: f32, end_angle: f32) {
+        self.0.set_angles(start_angle, end_angle);
+    }
+
+    /// Set the width of the line. Its colour comes from
+    /// [`Shape::set_color`].
+    pub fn set_thickness(&mut self, thickness: LineWidth) {
+        self.0.set_stroke(thickness, self.0.stroke_color);
+    }
+
+    /// Set the caps and dash pattern of the line.
+    pub fn set_stroke_style(&mut self, style: StrokeStyle) {
+        self.0.set_stroke_style(style);
+    }
+}
+
+// Circles, ellipses and sectors are closed shapes that can be filled,
+// outlined or both.
+macro_rules! impl_closed {
+    ($($shape:ident),*) => {
+        $(
+            impl $shape {
+                /// Release the buffer space used by this shape.
+                pub fn free(self, renderer: &mut Renderer) {
+                    self.0.free(renderer);
+                }
+
+                /// Colour the inside with a solid colour or a gradient.
+                /// Setting the colour replaces the fill.
+                pub fn set_fill(&mut self, fill: Fill) {
+                    self.0.set_fill(fill);
+                }
+
+                /// Choose whether to fill the shape, outline it, or both.
+                pub fn set_draw_mode(&mut self, draw_mode: DrawMode) {
+                    self.0.set_draw_mode(draw_mode);
+                }
+
+                /// Set the width and colour of the outline, which is centred
+                /// on the edge.
+                pub fn set_stroke(&mut self, width: LineWidth, color: Color) {
+                    self.0.set_stroke(width, color);
+                }
+
+                /// Set the joins and dash pattern of the outline.
+                pub fn set_stroke_style(&mut self, style: StrokeStyle) {
+                    self.0.set_stroke_style(style);
+                }
+            }
+        )*
+    };
+}
+
+impl_closed!(Circle, Ellipse, Sector);
+
+macro_rules! impl_shape {
+    ($($shape:ident),*) => {
+        $(
+            impl Shape for $shape {
+                fn set_position(&mut self, position: Vector2) {
+                    self.0.set_position(position);
+                }
+
+                fn resize(&mut self, size: Vector2) {
+                    self.0.resize(size);
+                }
+
+                fn set_color(&mut self, color: Color) {
+                    self.0.set_color(color);
+                }
+
+                fn set_z_index(&mut self, z_index: i32) {
+                    self.0.set_z_index(z_index);
+                }
+
+                fn draw(&mut self, renderer: &mut Renderer, parent_pos: Point2) {
+                    self.0.draw(renderer, parent_pos);
+                }
+            }
+        )*
+    };
+}
+
+impl_shape!(Circle, Ellipse, Sector, Arc);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A round shape 100 pixels across in a 200 pixel window.
+    fn round(kind: Kind, start_angle: f32, end_angle: f32) -> Round {
+        let mut round = Round::with_pixel_size(
+            kind,
+            true,
+            Vector2::new(0.5, 0.5),
+            Vector2::new(0.01, 0.01),
+            false,
+        );
+        round.set_angles(start_angle, end_angle);
+        round
+    }
+
+    /// Segments in a sweep of a 50 pixel radius.
+    fn segments(sweep: f32) -> usize {
+        let step = 2.0 * PI / arc_segments(50.0, 2.0 * PI) as f32;
+        (sweep / step).ceil() as usize
+    }
+
+    #[test]
+    fn sector_has_a_point_per_step_and_the_centre() {
+        let mut sector = round(Kind::Sector, 0.0, PI / 2.0);
+        sector.build(sector.start_angle, sector.end_angle);
+
+        let segments = segments(PI / 2.0);
+        assert_eq!(sector.mesh.vertices.len(), segments + 2);
+        assert_eq!(sector.mesh.indices.len(), segments * 3);
+    }
+
+    #[test]
+    fn arc_has_a_quad_per_step_and_a_join_between() {
+        let mut arc = round(Kind::Arc, 0.0, PI / 2.0);
+        arc.build(arc.start_angle, arc.end_angle);
+
+        // Butt caps add nothing, and each miter join is two triangles.
+        let segments = segments(PI / 2.0);
+        assert_eq!(arc.mesh.vertices.len(), 4 * segments + 4 * (segments - 1));
+        assert_eq!(arc.mesh.indices.len(), 6 * segments + 6 * (segments - 1));
+    }
+
+    #[test]
+    fn changing_the_angles_keeps_the_buffer_space() {
+        for &kind in &[Kind::Sector, Kind::Arc] {
+            let mut buffer = DualBuffer::new("Triangles", 1024, 4096);
+            let mut shape = round(kind, 0.0, PI / 4.0);
+            shape.write(&mut buffer, Point3D::default());
+            let handle = shape.allocation.handle();
+            assert!(handle.is_some());
+
+            for &end_angle in &[PI, 1.99 * PI, 2.0 * PI, 0.01] {
+                shape.set_angles(0.0, end_angle);
+                shape.write(&mut buffer, Point3D::default());
+                assert_eq!(shape.allocation.handle(), handle);
+            }
+        }
+    }
+
+    #[test]
+    fn styled_strokes_fit_the_buffer_space_at_any_angle() {
+        use crate::{LineCap, LineJoin};
+
+        let styles = [
+            StrokeStyle::solid()
+                .with_cap(LineCap::Round)
+                .with_join(LineJoin::Round),
+            StrokeStyle::dashed(6.0, 4.0).with_cap(LineCap::Round),
+            StrokeStyle::dashed(1.0, 1.0).with_cap(LineCap::Square),
+            StrokeStyle::dotted(3.0),
+            StrokeStyle::dash_dot(8.0, 3.0).with_join(LineJoin::Round),
+        ];
+        for &kind in &[Kind::Sector, Kind::Arc] {
+            for style in &styles {
+                let mut buffer = DualBuffer::new("Triangles", 1024, 4096);
+                let mut shape = round(kind, 0.3, 0.3 + PI / 4.0);
+                shape.set_draw_mode(DrawMode::Stroke);
+                shape.set_stroke(LineWidth::Pixels(8.0), Color::BLACK);
+                shape.set_stroke_style(style.clone());
+                shape.write(&mut buffer, Point3D::default());
+                let handle = shape.allocation.handle();
+
+                for i in 1..=40 {
+                    let sweep = 2.0 * PI * i as f32 / 40.0 - 0.05;
+                    for &end_angle in &[0.3 + sweep, 0.3 - sweep] {
+                        shape.set_angles(0.3, end_angle);
+                        shape.write(&mut buffer, Point3D::default());
+                        assert_eq!(
+                            shape.allocation.handle(),
+                            handle,
+                            "{:?} with {:?} to {}",
+                            kind,
+                            style,
+                            end_angle
+                        );
+                    }
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,79 @@
+//! One pixel wide transparent fringe for [`Antialiasing::Feathered`].
+//!
+//! [`Antialiasing::Feathered`]: crate::Antialiasing::Feathered
+
+use crate::{Vector2, Vertex};
+use cgmath::InnerSpace;
+
+/// Longest offset of a fringe vertex in pixels, so sharp corners don't spike.
+const MAX_MITER: f32 = 4.0;
+
+/// Append the triangles joining a closed convex `outline` to its fringe.
+///
+/// `outline` holds indices of the outline vertices and `first` is the index of
+/// the first fringe vertex. Fringe vertices follow the outline order.
+pub(crate) fn push_indices(indices: &mut Vec<u32>, outline: &[u32], first: u32) {
+    let len = outline.len() as u32;
+    for i in 0..len {
+        let j = (i + 1) % len;
+        let (a, b) = (outline[i as usize], outline[j as usize]);
+        let (fa, fb) = (first + i, first + j);
+        indices.extend_from_slice(&[a, b, fb, a, fb, fa]);
+    }
+}
+
+/// Append one fringe vertex for each vertex of a closed convex `outline`.
+///
+/// Fringe vertices sit one pixel outside the outline edges and have the
+/// colour of their outline vertex with zero alpha. `pixel` is the size of a
+/// pixel in the units of the vertex positions.
+pub(crate) fn push_vertices(vertices: &mut Vec<Vertex>, outline: &[u32], pixel: Vector2) {
+    let to_pixels =
+        |vertex: &Vertex| Vector2::new(vertex.position.x / pixel.x, vertex.position.y / pixel.y);
+    let points: Vec<Vector2> = outline
+        .iter()
+        .map(|&i| to_pixels(&vertices[i as usize]))
+        .collect();
+
+    // Outward normals depend on the winding of the outline.
+    let len = points.len();
+    let area: f32 = (0..len)
+        .map(|i| {
+            let (p, q) = (points[i], points[(i + 1) % len]);
+            p.x * q.y - q.x * p.y
+        })
+        .sum();
+    let winding = if area < 0.0 { -1.0 } else { 1.0 };
+    let edge_normal = |from: Vector2, to: Vector2| {
+        let edge = to - from;
+        if edge.magnitude2() > 0.0 {
+            Vector2::new(edge.y, -edge.x).normalize() * winding
+        } else {
+            Vector2::new(0.0, 0.0)
+        }
+    };
+
+    for i in 0..len {
+        let prev = points[(i + len - 1) % len];
+        let next = points[(i + 1) % len];
+        let before = edge_normal(prev, points[i]);
+        let after = edge_normal(points[i], next);
+
+        let mut offset = before + after;
+        if offset.magnitude2() > 0.0 {
+            // Lengthen the offset so both edges move out by a whole pixel.
+            offset = offset.normalize();
+            let cos = offset
+                .dot(after)
+                .max(offset.dot(before))
+                .max(1.0 / MAX_MITER);
+            offset /= cos;
+        }
+
+        let mut vertex = vertices[outline[i] as usize];
+        vertex.position.x += offset.x * pixel.x;
+        vertex.position.y += offset.y * pixel.y;
+        vertex.color.a = 0.0;
+        vertices.push(vertex);
+    }
+}
@@ -0,0 +1,533 @@
+//! Solid and gradient fills, drawn by colouring mesh vertices.
+
+use super::mesh::Mesh;
+use crate::{Color, Point2, Point3D, Vector2, Vertex};
+use cgmath::{EuclideanSpace, InnerSpace};
+use std::collections::HashMap;
+
+/// Largest error in gradient offset allowed where straight edges cut across
+/// the circles of a radial gradient.
+const RADIAL_TOLERANCE: f32 = 1.0 / 64.0;
+/// Most times the edges of a radial gradient are halved.
+const MAX_RADIAL_ROUNDS: u32 = 8;
+/// Largest distance from an edge, as a fraction of its length, at which the
+/// centre of a radial gradient counts as on the edge.
+const ON_EDGE_TOLERANCE: f32 = 1e-4;
+
+/// How the inside of a shape is coloured.
+///
+/// Gradient points are relative to the bounding box of the shape, from (0, 0)
+/// at the top left to (1, 1) at the bottom right, so gradients stretch with
+/// the shape. Stops are pairs of an offset along the gradient and a colour, in
+/// increasing order of offset. Before the first stop and after the last the
+/// colour stays the same.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Fill {
+    Solid(Color),
+    /// Offsets go from 0 at `start` to 1 at `end`.
+    LinearGradient {
+        start: Point2,
+        end: Point2,
+        stops: Vec<(f32, Color)>,
+    },
+    /// Offsets go from 0 at `center` to 1 at `radius` from it. The gradient
+    /// is elliptical in shapes that aren't square.
+    RadialGradient {
+        center: Point2,
+        radius: f32,
+        stops: Vec<(f32, Color)>,
+    },
+}
+
+impl Fill {
+    /// Offset along the gradient of a point in the bounding box.
+    fn offset_at(&self, point: Vector2) -> f32 {
+        match self {
+            Fill::Solid(_) => 0.0,
+            Fill::LinearGradient { start, end, .. } => {
+                let axis = end - start;
+                if axis.magnitude2() <= f32::EPSILON {
+                    return 0.0;
+                }
+                (point - start.to_vec()).dot(axis) / axis.magnitude2()
+            }
+            Fill::RadialGradient { center, radius, .. } => {
+                if *radius <= 0.0 {
+                    return 1.0;
+                }
+                (point - center.to_vec()).magnitude() / radius
+            }
+        }
+    }
+
+    fn stops(&self) -> &[(f32, Color)] {
+        match self {
+            Fill::Solid(_) => &[],
+            Fill::LinearGradient { stops, .. } | Fill::RadialGradient { stops, .. } => stops,
+        }
+    }
+
+    fn color_at(&self, offset: f32) -> Color {
+        let stops = match self {
+            Fill::Solid(color) => return *color,
+            _ => self.stops(),
+        };
+        let (first, last) = match (stops.first(), stops.last()) {
+            (Some(first), Some(last)) => (*first, *last),
+            _ => return Color::TRANSPARENT,
+        };
+        if offset <= first.0 {
+            return first.1;
+        }
+        for pair in stops.windows(2) {
+            let ((from, from_color), (to, to_color)) = (pair[0], pair[1]);
+            if offset <= to {
+                if to <= from {
+                    return to_color;
+                }
+                return from_color.lerp(to_color, (offset - from) / (to - from));
+            }
+        }
+        last.1
+    }
+
+    /// Offset clamped to the stops, beyond which the colour doesn't change.
+    fn clamped_offset(&self, offset: f32) -> f32 {
+        match (self.stops().first(), self.stops().last()) {
+            (Some(first), Some(last)) => offset.clamp(first.0, last.0.max(first.0)),
+            _ => 0.0,
+        }
+    }
+}
+
+/// Vertices that [`apply`] added part way along edges of the mesh.
+#[derive(Debug, Default)]
+pub(crate) struct Splits(HashMap<(u32, u32), u32>);
+
+impl Splits {
+    /// The closed `outline` with the vertices added along its edges inserted
+    /// in order, so geometry built along it meets the fill without cracks.
+    pub fn outline(&self, outline: &[u32]) -> Vec<u32> {
+        let mut result = Vec::with_capacity(outline.len());
+        for i in 0..outline.len() {
+            self.push_edge(&mut result, outline[i], outline[(i + 1) % outline.len()]);
+        }
+        result
+    }
+
+    /// Push `from` and the vertices between it and `to`, but not `to`.
+    fn push_edge(&self, result: &mut Vec<u32>, from: u32, to: u32) {
+        match self.0.get(&(from.min(to), from.max(to))) {
+            Some(&middle) => {
+                self.push_edge(result, from, middle);
+                self.push_edge(result, middle, to);
+            }
+            None => result.push(from),
+        }
+    }
+}
+
+/// Colour the triangles in `mesh.indices[first_index..]` with `fill`.
+///
+/// Vertex colours are interpolated linearly, so gradients split the triangles
+/// along every stop, and radial gradients also split them until their edges
+/// follow the circles closely. Edges are split the same way in every triangle
+/// that shares them, so no cracks open up. The returned splits give the same
+/// points on the outline of the mesh.
+pub(crate) fn apply(mesh: &mut Mesh, first_index: usize, fill: &Fill) -> Splits {
+    let mut triangles: Vec<[u32; 3]> = mesh.indices[first_index..]
+        .chunks_exact(3)
+        .map(|triangle| [triangle[0], triangle[1], triangle[2]])
+        .collect();
+    mesh.indices.truncate(first_index);
+
+    let (min, max) = triangles.iter().flatten().fold(
+        (
+            Vector2::new(f32::MAX, f32::MAX),
+            Vector2::new(f32::MIN, f32::MIN),
+        ),
+        |(min, max), &i| {
+            let position = mesh.vertices[i as usize].position;
+            (
+                Vector2::new(min.x.min(position.x), min.y.min(position.y)),
+                Vector2::new(max.x.max(position.x), max.y.max(position.y)),
+            )
+        },
+    );
+    let size = max - min;
+    // Vertex positions have y pointing up, and the bounding box down.
+    let to_box = |position: Point3D| {
+        Vector2::new(
+            if size.x > 0.0 {
+                (position.x - min.x) / size.x
+            } else {
+                0.0
+            },
+            if size.y > 0.0 {
+                (max.y - position.y) / size.y
+            } else {
+                0.0
+            },
+        )
+    };
+    let offset = |mesh: &Mesh, i: u32| fill.offset_at(to_box(mesh.vertices[i as usize].position));
+    let mut splits = Splits::default();
+
+    if let Fill::RadialGradient { center, .. } = fill {
+        // The gradient turns around at its centre, so it needs a vertex there.
+        let center = Vector2::new(min.x + center.x * size.x, max.y - center.y * size.y);
+        if let Some(i) = triangles
+            .iter()
+            .position(|triangle| contains(mesh, triangle, center))
+        {
+            let triangle = triangles[i];
+            let corner = |mesh: &Mesh, i: u32| {
+                let position = mesh.vertices[i as usize].position;
+                Vector2::new(position.x, position.y)
+            };
+            let on_corner = triangle
+                .iter()
+                .any(|&i| (corner(mesh, i) - center).magnitude2() <= f32::EPSILON);
+            let on_edge = (0..3)
+                .map(|k| (triangle[k], triangle[(k + 1) % 3]))
+                .find(|&(a, b)| {
+                    let (a, b) = (corner(mesh, a), corner(mesh, b));
+                    let side = (b.x - a.x) * (center.y - a.y) - (b.y - a.y) * (center.x - a.x);
+                    side.abs() <= ON_EDGE_TOLERANCE * (b - a).magnitude2()
+                });
+            if on_corner {
+                // There is a vertex there already.
+            } else if let Some((a, b)) = on_edge {
+                // Split the edge, and so both triangles sharing it, at the
+                // centre, so neither is left with a vertex on its side.
+                let edge = (a.min(b), a.max(b));
+                triangles = split_edges(mesh, &triangles, &mut splits, |mesh, from, to| {
+                    if (from, to) != edge {
+                        return None;
+                    }
+                    let (from, to) = (corner(mesh, from), corner(mesh, to));
+                    Some((center - from).dot(to - from) / (to - from).magnitude2())
+                });
+            } else {
+                let [a, b, c] = triangles.swap_remove(i);
+                let mut vertex = mesh.vertices[a as usize];
+                vertex.position.x = center.x;
+                vertex.position.y = center.y;
+                mesh.vertices.push(vertex);
+                let p = mesh.vertices.len() as u32 - 1;
+                triangles.extend_from_slice(&[[a, b, p], [b, c, p], [c, a, p]]);
+            }
+        }
+
+        for _ in 0..MAX_RADIAL_ROUNDS {
+            let count = triangles.len();
+            triangles = split_edges(mesh, &triangles, &mut splits, |mesh, from, to| {
+                let mut middle = mesh.vertices[from as usize].position;
+                let end = mesh.vertices[to as usize].position;
+                middle.x = (middle.x + end.x) / 2.0;
+                middle.y = (middle.y + end.y) / 2.0;
+                let expected = (fill.clamped_offset(offset(mesh, from))
+                    + fill.clamped_offset(offset(mesh, to)))
+                    / 2.0;
+                let actual = fill.clamped_offset(fill.offset_at(to_box(middle)));
+                if (actual - expected).abs() > RADIAL_TOLERANCE {
+                    Some(0.5)
+                } else {
+                    None
+                }
+            });
+            if triangles.len() == count {
+                break;
+            }
+        }
+    }
+
+    for &(stop, _) in fill.stops() {
+        triangles = split_edges(mesh, &triangles, &mut splits, |mesh, from, to| {
+            let (from, to) = (offset(mesh, from), offset(mesh, to));
+            if (from < stop && stop < to) || (to < stop && stop < from) {
+                Some((stop - from) / (to - from))
+            } else {
+                None
+            }
+        });
+    }
+
+    for &i in triangles.iter().flatten() {
+        mesh.vertices[i as usize].color = fill.color_at(offset(mesh, i));
+    }
+    mesh.indices.extend(triangles.iter().flatten());
+    splits
+}
+
+/// Whether `point` is inside or on the edge of a triangle of either winding.
+fn contains(mesh: &Mesh, triangle: &[u32; 3], point: Vector2) -> bool {
+    let corner = |i: usize| {
+        let position = mesh.vertices[triangle[i] as usize].position;
+        Vector2::new(position.x, position.y)
+    };
+    let sides: Vec<f32> = (0..3)
+        .map(|i| {
+            let (a, b) = (corner(i), corner((i + 1) % 3));
+            (b.x - a.x) * (point.y - a.y) - (b.y - a.y) * (point.x - a.x)
+        })
+        .collect();
+    sides.iter().all(|&side| side >= 0.0) || sides.iter().all(|&side| side <= 0.0)
+}
+
+/// Split each edge where `split_at` returns a fraction of the way along it,
+/// and turn each triangle into the triangles between its old and new vertices.
+///
+/// `split_at` is called with the lower vertex index first, so an edge is split
+/// at the same place from either side. New vertices are added to `splits`.
+fn split_edges(
+    mesh: &mut Mesh,
+    triangles: &[[u32; 3]],
+    splits: &mut Splits,
+    split_at: impl Fn(&Mesh, u32, u32) -> Option<f32>,
+) -> Vec<[u32; 3]> {
+    let mut edges: HashMap<(u32, u32), Option<u32>> = HashMap::new();
+    let mut split = |mesh: &mut Mesh, a: u32, b: u32| {
+        let edge = (a.min(b), a.max(b));
+        *edges.entry(edge).or_insert_with(|| {
+            let t = split_at(mesh, edge.0, edge.1)?;
+            let (from, to) = (
+                mesh.vertices[edge.0 as usize],
+                mesh.vertices[edge.1 as usize],
+            );
+            mesh.vertices.push(Vertex {
+                position: Point3D {
+                    x: from.position.x + (to.position.x - from.position.x) * t,
+                    y: from.position.y + (to.position.y - from.position.y) * t,
+                    z: from.position.z,
+                },
+                tex_coords: [
+                    from.tex_coords[0] + (to.tex_coords[0] - from.tex_coords[0]) * t,
+                    from.tex_coords[1] + (to.tex_coords[1] - from.tex_coords[1]) * t,
+                ],
+                ..from
+            });
+            Some(mesh.vertices.len() as u32 - 1)
+        })
+    };
+
+    let mut result = Vec::with_capacity(triangles.len());
+    for &[a, b, c] in triangles {
+        let middles = (split(mesh, a, b), split(mesh, b, c), split(mesh, c, a));
+        match middles {
+            (None, None, None) => result.push([a, b, c]),
+            (Some(x), None, None) => result.extend_from_slice(&[[a, x, c], [x, b, c]]),
+            (None, Some(y), None) => result.extend_from_slice(&[[b, y, a], [y, c, a]]),
+            (None, None, Some(z)) => result.extend_from_slice(&[[c, z, b], [z, a, b]]),
+            (Some(x), Some(y), None) => {
+                result.extend_from_slice(&[[x, b, y], [a, x, y], [a, y, c]])
+            }
+            (None, Some(y), Some(z)) => {
+                result.extend_from_slice(&[[y, c, z], [b, y, z], [b, z, a]])
+            }
+            (Some(x), None, Some(z)) => {
+                result.extend_from_slice(&[[z, a, x], [c, z, x], [c, x, b]])
+            }
+            (Some(x), Some(y), Some(z)) => {
+                result.extend_from_slice(&[[a, x, z], [x, b, y], [z, y, c], [x, y, z]])
+            }
+        }
+    }
+    for (edge, middle) in edges {
+        if let Some(middle) = middle {
+            splits.0.insert(edge, middle);
+        }
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::feather;
+    use super::*;
+
+    /// A unit square in the y up coordinates of mesh vertices, as two
+    /// triangles sharing a diagonal.
+    fn square() -> Mesh {
+        let mut mesh = Mesh::default();
+        for &(x, y) in &[(0.0, 0.0), (1.0, 0.0), (1.0, 1.0), (0.0, 1.0)] {
+            mesh.push_vertex(Vector2::new(x, y), Color::WHITE);
+        }
+        mesh.push_triangle(0, 1, 2);
+        mesh.push_triangle(0, 2, 3);
+        mesh
+    }
+
+    fn position(mesh: &Mesh, i: u32) -> Vector2 {
+        let position = mesh.vertices[i as usize].position;
+        Vector2::new(position.x, position.y)
+    }
+
+    fn side(a: Vector2, b: Vector2, point: Vector2) -> f32 {
+        (b.x - a.x) * (point.y - a.y) - (b.y - a.y) * (point.x - a.x)
+    }
+
+    /// Check that no triangle is degenerate and that no vertex lies on the
+    /// side of a triangle it isn't a corner of.
+    fn assert_well_formed(mesh: &Mesh) {
+        for triangle in mesh.indices.chunks(3) {
+            let [a, b, c] = [0, 1, 2].map(|k| position(mesh, triangle[k]));
+            assert!(side(a, b, c).abs() > 1e-9, "{:?} has no area", triangle);
+
+            for i in 0..mesh.vertices.len() as u32 {
+                if triangle.contains(&i) {
+                    continue;
+                }
+                let point = position(mesh, i);
+                for &(from, to) in &[(a, b), (b, c), (c, a)] {
+                    let along = (point - from).dot(to - from) / (to - from).magnitude2();
+                    let distance = side(from, to, point).abs() / (to - from).magnitude();
+                    let on_side = distance < 1e-6 && along > 0.0 && along < 1.0;
+                    assert!(!on_side, "vertex {} is on a side of {:?}", i, triangle);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn colors_between_stops_are_interpolated() {
+        let fill = Fill::LinearGradient {
+            start: Point2::new(0.0, 0.0),
+            end: Point2::new(1.0, 0.0),
+            stops: vec![(0.2, Color::RED), (0.6, Color::BLUE)],
+        };
+        let color = fill.color_at(0.4);
+        assert!((color.r - 0.5).abs() < 1e-6 && (color.b - 0.5).abs() < 1e-6);
+        assert_eq!(fill.color_at(0.6), Color::BLUE);
+    }
+
+    #[test]
+    fn colors_are_clamped_outside_the_stops() {
+        let fill = Fill::LinearGradient {
+            start: Point2::new(0.0, 0.0),
+            end: Point2::new(1.0, 0.0),
+            stops: vec![(0.2, Color::RED), (0.6, Color::BLUE)],
+        };
+        assert_eq!(fill.color_at(-1.0), Color::RED);
+        assert_eq!(fill.color_at(0.1), Color::RED);
+        assert_eq!(fill.color_at(0.9), Color::BLUE);
+        assert_eq!(fill.color_at(2.0), Color::BLUE);
+        assert_eq!(fill.clamped_offset(0.0), 0.2);
+        assert_eq!(fill.clamped_offset(1.0), 0.6);
+    }
+
+    #[test]
+    fn linear_gradients_split_triangles_at_stops() {
+        let fill = Fill::LinearGradient {
+            start: Point2::new(0.0, 0.0),
+            end: Point2::new(1.0, 0.0),
+            stops: vec![(0.0, Color::RED), (0.5, Color::LIME), (1.0, Color::BLUE)],
+        };
+        let mut mesh = square();
+        apply(&mut mesh, 0, &fill);
+        assert_well_formed(&mesh);
+
+        // The bottom, top and diagonal edges each gain a vertex on the stop.
+        assert_eq!(mesh.vertices.len(), 7);
+        for vertex in &mesh.vertices[4..] {
+            assert!((vertex.position.x - 0.5).abs() < 1e-6);
+            assert_eq!(vertex.color, Color::LIME);
+        }
+        // No triangle reaches across the stop.
+        for triangle in mesh.indices.chunks(3) {
+            let xs: Vec<f32> = triangle.iter().map(|&i| position(&mesh, i).x).collect();
+            assert!(xs.iter().all(|&x| x <= 0.5 + 1e-6) || xs.iter().all(|&x| x >= 0.5 - 1e-6));
+        }
+    }
+
+    #[test]
+    fn radial_center_on_a_shared_edge_splits_both_triangles() {
+        let fill = Fill::RadialGradient {
+            center: Point2::new(0.5, 0.5),
+            radius: 0.5,
+            stops: vec![(0.0, Color::WHITE), (1.0, Color::BLACK)],
+        };
+        let mut mesh = square();
+        apply(&mut mesh, 0, &fill);
+        assert_well_formed(&mesh);
+
+        let center = mesh
+            .vertices
+            .iter()
+            .position(|vertex| {
+                (vertex.position.x - 0.5).abs() < 1e-6 && (vertex.position.y - 0.5).abs() < 1e-6
+            })
+            .expect("no vertex at the centre");
+        assert_eq!(mesh.vertices[center].color, Color::WHITE);
+        // The triangles around the centre cover a full turn, so it is inside
+        // both halves of the square.
+        let center = center as u32;
+        let turn: f32 = mesh
+            .indices
+            .chunks(3)
+            .filter(|triangle| triangle.contains(&center))
+            .map(|triangle| {
+                let others: Vec<Vector2> = triangle
+                    .iter()
+                    .filter(|&&i| i != center)
+                    .map(|&i| position(&mesh, i) - position(&mesh, center))
+                    .collect();
+                others[0].angle(others[1]).0
+            })
+            .sum();
+        assert!((turn - 2.0 * std::f32::consts::PI).abs() < 1e-4);
+    }
+
+    #[test]
+    fn radial_center_inside_a_triangle_splits_it() {
+        let fill = Fill::RadialGradient {
+            center: Point2::new(0.75, 0.5),
+            radius: 0.5,
+            stops: vec![(0.0, Color::WHITE), (1.0, Color::BLACK)],
+        };
+        let mut mesh = square();
+        apply(&mut mesh, 0, &fill);
+        assert_well_formed(&mesh);
+        assert!(mesh.vertices.iter().any(|vertex| {
+            (vertex.position.x - 0.75).abs() < 1e-6
+                && (vertex.position.y - 0.5).abs() < 1e-6
+                && vertex.color == Color::WHITE
+        }));
+    }
+
+    #[test]
+    fn split_outline_keeps_the_fringe_free_of_cracks() {
+        let fill = Fill::RadialGradient {
+            center: Point2::new(0.3, 0.6),
+            radius: 0.8,
+            stops: vec![(0.2, Color::RED), (0.5, Color::BLUE), (0.9, Color::GREEN)],
+        };
+        let mut mesh = square();
+        let outline = apply(&mut mesh, 0, &fill).outline(&[0, 1, 2, 3]);
+
+        // Every vertex on the edge of the square is in the outline, in order
+        // around it.
+        let on_edge = |i: &u32| {
+            let p = position(&mesh, *i);
+            p.x.abs() < 1e-6
+                || p.y.abs() < 1e-6
+                || (p.x - 1.0).abs() < 1e-6
+                || (p.y - 1.0).abs() < 1e-6
+        };
+        let mut expected: Vec<u32> = (0..mesh.vertices.len() as u32).filter(on_edge).collect();
+        let angle = |i: &u32| {
+            let p = position(&mesh, *i) - Vector2::new(0.5, 0.5);
+            (-p.y).atan2(-p.x)
+        };
+        expected.sort_by(|a, b| angle(a).total_cmp(&angle(b)));
+        let start = expected.iter().position(|&i| i == 0).unwrap();
+        expected.rotate_left(start);
+        assert!(expected.len() > 4);
+        assert_eq!(outline, expected);
+
+        let first = mesh.vertices.len() as u32;
+        feather::push_indices(&mut mesh.indices, &outline, first);
+        feather::push_vertices(&mut mesh.vertices, &outline, Vector2::new(0.01, 0.01));
+        assert_well_formed(&mesh);
+    }
+}
@@ -0,0 +1,226 @@
+use super::fill::{self, Fill};
+use super::mesh::{Mesh, MeshAllocation};
+use super::stroke;
+use super::triangulate::{triangulate, PolygonError};
+use crate::{
+    Color, DrawMode, LineWidth, Point2, Point3D, Renderer, Shape, StrokeStyle, Vector2, Vertex,
+};
+use cgmath::ElementWise;
+
+/// A polygon through any number of points, with optional holes.
+///
+/// The outline and holes may be concave but must not cross or touch
+/// themselves or each other. Points are relative to the position of the shape.
+#[derive(Debug)]
+pub struct FreePolygon {
+    position: Vector2,
+    /// Bounding box of the outline, used to scale the points on resize.
+    size: Vector2,
+    color: Color,
+    fill: Option<Fill>,
+    /// Points of the outline followed by the points of each hole.
+    vertices: Vec<Vertex>,
+    /// Number of points in the outline and in each hole.
+    rings: Vec<u32>,
+    indices: Vec<u32>,
+    draw_mode: DrawMode,
+    stroke_width: LineWidth,
+    stroke_color: Color,
+    stroke_style: StrokeStyle,
+    /// Pixel size the outline was built for.
+    pixel_size: Vector2,
+    mesh: Mesh,
+    allocation: MeshAllocation,
+    z_index: i32,
+    dirty: bool,
+}
+
+impl FreePolygon {
+    pub fn new(renderer: &mut Renderer, outline: &[Point2]) -> Result<Self, PolygonError> {
+        Self::with_holes(renderer, outline, &[])
+    }
+
+    pub fn with_holes(
+        renderer: &mut Renderer,
+        outline: &[Point2],
+        holes: &[Vec<Point2>],
+    ) -> Result<Self, PolygonError> {
+        let mut polygon = Self {
+            position: Vector2::new(0.0, 0.0),
+            size: Vector2::new(0.0, 0.0),
+            color: Color::default(),
+            fill: None,
+            vertices: Vec::new(),
+            rings: Vec::new(),
+            indices: Vec::new(),
+            draw_mode: DrawMode::default(),
+            stroke_width: LineWidth::Pixels(1.0),
+            stroke_color: Color::BLACK,
+            stroke_style: StrokeStyle::default(),
+            pixel_size: renderer.pixel_size(),
+            mesh: Mesh::default(),
+            allocation: MeshAllocation::default(),
+            z_index: 0,
+            dirty: true,
+        };
+        polygon.set_points(outline, holes)?;
+        Ok(polygon)
+    }
+
+    /// Release the buffer space used by this shape.
+    pub fn free(mut self, renderer: &mut Renderer) {
+        self.allocation.free(&mut renderer.triangles_buffer);
+    }
+
+    /// Replace the outline and holes. On error the polygon is left unchanged.
+    pub fn set_points(
+        &mut self,
+        outline: &[Point2],
+        holes: &[Vec<Point2>],
+    ) -> Result<(), PolygonError> {
+        let rings: Vec<Vec<Vector2>> = std::iter::once(outline)
+            .chain(holes.iter().map(Vec::as_slice))
+            .map(|ring| {
+                ring.iter()
+                    .map(|point| Vector2::new(point.x, point.y))
+                    .collect()
+            })
+            .collect();
+        let ring_slices: Vec<&[Vector2]> = rings.iter().map(Vec::as_slice).collect();
+        self.indices = triangulate(&ring_slices)?;
+
+        let color = self.color;
+        self.vertices = rings
+            .iter()
+            .flatten()
+            .map(|point| Vertex {
+                position: Point3D::from(*point),
+                color,
+                ..Vertex::default()
+            })
+            .collect();
+        self.rings = rings.iter().map(|ring| ring.len() as u32).collect();
+
+        let (min, max) = rings[0].iter().fold(
+            (
+                Vector2::new(f32::MAX, f32::MAX),
+                Vector2::new(f32::MIN, f32::MIN),
+            ),
+            |(min, max), point| {
+                (
+                    Vector2::new(min.x.min(point.x), min.y.min(point.y)),
+                    Vector2::new(max.x.max(point.x), max.y.max(point.y)),
+                )
+            },
+        );
+        self.size = max - min;
+        self.dirty = true;
+        Ok(())
+    }
+
+    /// Number of points in the outline and holes together.
+    pub fn vertex_count(&self) -> u32 {
+        self.vertices.len() as u32
+    }
+
+    /// Colour the inside with a solid colour or a gradient. Setting the
+    /// colour replaces the fill.
+    pub fn set_fill(&mut self, fill: Fill) {
+        self.fill = Some(fill);
+        self.dirty = true;
+    }
+
+    /// Choose whether to fill the shape, outline it, or both.
+    pub fn set_draw_mode(&mut self, draw_mode: DrawMode) {
+        self.draw_mode = draw_mode;
+        self.dirty = true;
+    }
+
+    /// Set the width and colour of the outline, which is centred on the edges
+    /// of the outline and holes.
+    pub fn set_stroke(&mut self, width: LineWidth, color: Color) {
+        self.stroke_width = width;
+        self.stroke_color = color;
+        self.dirty = true;
+    }
+
+    /// Set the joins and dash pattern of the outline.
+    pub fn set_stroke_style(&mut self, style: StrokeStyle) {
+        self.stroke_style = style;
+        self.dirty = true;
+    }
+}
+
+impl Shape for FreePolygon {
+    fn set_position(&mut self, position: Vector2) {
+        self.position = position;
+        self.dirty = true;
+    }
+
+    fn resize(&mut self, size: Vector2) {
+        let scale = size.div_element_wise(self.size);
+        self.size = size;
+        for vertex in &mut self.vertices {
+            vertex.position.x *= scale.x;
+            vertex.position.y *= scale.y;
+        }
+        self.dirty = true;
+    }
+
+    fn set_color(&mut self, color: Color) {
+        self.color = color;
+        self.fill = None;
+        for vertex in &mut self.vertices {
+            vertex.color = color;
+        }
+        self.dirty = true;
+    }
+
+    fn set_z_index(&mut self, z_index: i32) {
+        self.z_index = z_index;
+        self.dirty = true;
+    }
+
+    fn draw(&mut self, renderer: &mut Renderer, parent_pos: Point2) {
+        // The outline has to be rebuilt when the window size changes.
+        if self.draw_mode.strokes() && self.pixel_size != renderer.pixel_size() {
+            self.pixel_size = renderer.pixel_size();
+            self.dirty = true;
+        }
+        if !self.dirty {
+            return;
+        }
+        self.dirty = false;
+
+        self.mesh.clear();
+        self.mesh.vertices.extend_from_slice(&self.vertices);
+        if self.draw_mode.fills() {
+            self.mesh.indices.extend_from_slice(&self.indices);
+            if let Some(fill) = &self.fill {
+                fill::apply(&mut self.mesh, 0, fill);
+            }
+        }
+        if self.draw_mode.strokes() {
+            let mut first = 0;
+            for &len in &self.rings {
+                let ring: Vec<u32> = (first..(first + len)).collect();
+                stroke::stroke_outline(
+                    &mut self.mesh,
+                    &ring,
+                    self.stroke_color,
+                    self.stroke_width,
+                    &self.stroke_style,
+                    self.pixel_size,
+                );
+                first += len;
+            }
+        }
+
+        self.allocation.write(
+            &mut renderer.triangles_buffer,
+            &self.mesh,
+            self.z_index,
+            Point3D::from(parent_pos + self.position),
+        );
+    }
+}
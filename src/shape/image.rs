@@ -0,0 +1,143 @@
+use super::mesh::{Mesh, MeshAllocation};
+use crate::{
+    Color, Filter, Point2, Point3D, Renderer, Shape, TextureId, TextureRect, Vector2, Vertex,
+};
+
+/// An image from the texture atlas stretched over its box.
+///
+/// Images are drawn with the other triangles, so they layer with shapes by z
+/// index. The colour set with [`Shape::set_color`] tints the image, and is
+/// white by default.
+#[derive(Debug)]
+pub struct Image {
+    position: Vector2,
+    size: Vector2,
+    texture: TextureId,
+    /// Part of the image to draw, such as one frame of a sprite sheet.
+    source: Option<TextureRect>,
+    filter: Filter,
+    tint: Color,
+    /// Atlas generation the texture coordinates were looked up in.
+    atlas_generation: u32,
+    mesh: Mesh,
+    allocation: MeshAllocation,
+    z_index: i32,
+    dirty: bool,
+}
+
+impl Image {
+    /// Draw an image added with [`Renderer::add_image`] or one of the
+    /// `load_image` methods.
+    pub fn new(texture: TextureId, size: Vector2) -> Self {
+        Self {
+            position: Vector2::new(0.0, 0.0),
+            size,
+            texture,
+            source: None,
+            filter: Filter::default(),
+            tint: Color::WHITE,
+            atlas_generation: 0,
+            mesh: Mesh::default(),
+            allocation: MeshAllocation::default(),
+            z_index: 0,
+            dirty: true,
+        }
+    }
+
+    /// Release the buffer space used by this shape.
+    pub fn free(mut self, renderer: &mut Renderer) {
+        self.allocation.free(&mut renderer.triangles_buffer);
+    }
+
+    pub fn set_texture(&mut self, texture: TextureId) {
+        self.texture = texture;
+        self.dirty = true;
+    }
+
+    /// Draw only part of the image, in pixels from its top left corner, or
+    /// all of it with `None`. Parts reaching outside the image are cut off.
+    pub fn set_source(&mut self, source: Option<TextureRect>) {
+        self.source = source;
+        self.dirty = true;
+    }
+
+    /// Choose how the image is sampled when it's scaled.
+    pub fn set_filter(&mut self, filter: Filter) {
+        self.filter = filter;
+        self.dirty = true;
+    }
+}
+
+impl Shape for Image {
+    fn set_position(&mut self, position: Vector2) {
+        self.position = position;
+        self.dirty = true;
+    }
+
+    fn resize(&mut self, size: Vector2) {
+        self.size = size;
+        self.dirty = true;
+    }
+
+    fn set_color(&mut self, color: Color) {
+        self.tint = color;
+        self.dirty = true;
+    }
+
+    fn set_z_index(&mut self, z_index: i32) {
+        self.z_index = z_index;
+        self.dirty = true;
+    }
+
+    fn draw(&mut self, renderer: &mut Renderer, parent_pos: Point2) {
+        // The texture may have been removed, and its space given to another
+        // image.
+        if self.atlas_generation != renderer.atlas_generation() {
+            self.atlas_generation = renderer.atlas_generation();
+            self.dirty = true;
+        }
+        if !self.dirty {
+            return;
+        }
+        self.dirty = false;
+
+        // Removed images and images from another renderer are drawn in the
+        // tint colour alone.
+        let uv_rect = renderer
+            .uv_rect(self.texture, self.source)
+            .unwrap_or_default();
+        let size = Point3D::from(self.size);
+        let (color, filter) = (self.tint, self.filter.to_vertex());
+        let corner = |x: f32, y: f32| {
+            let mut vertex = Vertex {
+                position: Point3D {
+                    x: x * size.x,
+                    y: y * size.y,
+                    z: 0.0,
+                },
+                color,
+                filter,
+                ..Vertex::default()
+            };
+            uv_rect.apply(&mut vertex, x, y);
+            vertex
+        };
+
+        self.mesh.clear();
+        self.mesh.vertices.extend_from_slice(&[
+            corner(0.0, 0.0),
+            corner(0.0, 1.0),
+            corner(1.0, 1.0),
+            corner(1.0, 0.0),
+        ]);
+        self.mesh.push_triangle(0, 1, 2);
+        self.mesh.push_triangle(0, 2, 3);
+
+        self.allocation.write(
+            &mut renderer.triangles_buffer,
+            &self.mesh,
+            self.z_index,
+            Point3D::from(parent_pos + self.position),
+        );
+    }
+}
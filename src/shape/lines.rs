@@ -1,4 +1,8 @@
-use crate::{Color, Point2, Point3D, Renderer, Shape, Vector2, Vertex};
+use super::mesh::{Mesh, MeshAllocation, HIDDEN};
+use super::stroke::{self, LineWidth, StrokeStyle};
+use crate::{
+    AllocationHandle, Color, DualBuffer, Point2, Point3D, Renderer, Shape, Vector2, Vertex,
+};
 use cgmath::ElementWise;
 
 #[derive(Debug)]
@@ -6,34 +10,82 @@ pub struct Lines {
     position: Vector2,
     size: Vector2,
     vertices: Vec<Vertex>,
-    buffer_handle: usize,
+    /// Width of each line. Lines without a width are one pixel wide.
+    widths: Vec<Option<LineWidth>>,
+    style: StrokeStyle,
+    buffer_handle: AllocationHandle,
+    /// Wide lines, tessellated into the triangles buffer.
+    stroke: MeshAllocation,
+    /// Dashes of one pixel lines, in the lines buffer.
+    dashes: MeshAllocation,
+    mesh: Mesh,
+    pixel_size: Vector2,
+    z_index: i32,
     dirty: bool,
 }
 
 impl Lines {
     pub fn new(renderer: &mut Renderer, size: Vector2, line_count: usize) -> Self {
+        let pixel_size = renderer.pixel_size();
+        Self::with_buffer(&mut renderer.lines_buffer, pixel_size, size, line_count)
+    }
+
+    fn with_buffer(
+        buffer: &mut DualBuffer,
+        pixel_size: Vector2,
+        size: Vector2,
+        line_count: usize,
+    ) -> Self {
         let vertex_count = line_count * 2;
         let index_count = vertex_count;
 
-        let buffer_handle = renderer
-            .lines_buffer
-            .alloc(vertex_count as u32, index_count as u32)
-            .unwrap();
-
-        renderer.lines_buffer.write_indices(
-            buffer_handle,
-            &(0..(index_count as u32)).collect::<Vec<u32>>(),
-        );
+        // The extra vertex is off screen, for the ends of lines that aren't
+        // in the line list.
+        let buffer_handle = buffer
+            .alloc(vertex_count as u32 + 1, index_count as u32)
+            .expect("Failed to allocate buffer space.");
+        let hidden = Vertex {
+            position: HIDDEN,
+            ..Vertex::default()
+        };
+        buffer
+            .write_vertices_at(
+                buffer_handle,
+                vertex_count as u32,
+                &[hidden],
+                Point3D::default(),
+            )
+            .expect("Failed to write vertices.");
+        buffer
+            .write_indices(buffer_handle, &vec![vertex_count as u32; index_count])
+            .expect("Failed to write indices.");
 
         Self {
             position: Vector2::new(0.0, 0.0),
             size,
             vertices: vec![Vertex::default(); vertex_count],
+            widths: vec![None; line_count],
+            style: StrokeStyle::default(),
             buffer_handle,
+            stroke: MeshAllocation::default(),
+            dashes: MeshAllocation::default(),
+            mesh: Mesh::default(),
+            pixel_size,
+            z_index: 0,
             dirty: true,
         }
     }
 
+    /// Release the buffer space used by this shape.
+    pub fn free(mut self, renderer: &mut Renderer) {
+        self.stroke.free(&mut renderer.triangles_buffer);
+        self.dashes.free(&mut renderer.lines_buffer);
+        renderer
+            .lines_buffer
+            .free(self.buffer_handle)
+            .expect("Failed to free buffer allocation.");
+    }
+
     pub fn set_line_position(&mut self, mut line_index: usize, start: Point2, end: Point2) {
         line_index *= 2;
         self.vertices[line_index].position = start.into();
@@ -47,6 +99,86 @@ impl Lines {
         self.vertices[line_index + 1].color = color;
         self.dirty = true;
     }
+
+    /// Set the width of one line. `None` draws a one pixel line.
+    pub fn set_line_width(&mut self, line_index: usize, width: Option<LineWidth>) {
+        self.widths[line_index] = width;
+        self.dirty = true;
+    }
+
+    /// Set the width of every line. `None` draws one pixel lines.
+    pub fn set_width(&mut self, width: Option<LineWidth>) {
+        for line_width in &mut self.widths {
+            *line_width = width;
+        }
+        self.dirty = true;
+    }
+
+    /// Line list indices of the solid one pixel lines. Wide and dashed lines
+    /// have both ends at the off screen vertex after the lines' own, so they
+    /// draw nothing.
+    fn line_indices(&self) -> Vec<u32> {
+        let hidden = self.vertices.len() as u32;
+        let dashed = !self.style.is_solid();
+        let mut indices = Vec::with_capacity(self.vertices.len());
+        for (i, width) in self.widths.iter().enumerate() {
+            let start = i as u32 * 2;
+            if width.is_some() || dashed {
+                indices.extend_from_slice(&[hidden, hidden]);
+            } else {
+                indices.extend_from_slice(&[start, start + 1]);
+            }
+        }
+        indices
+    }
+
+    /// Ends of a line in pixels.
+    fn ends(&self, line_index: usize) -> Vec<(Vector2, Color)> {
+        self.vertices[(line_index * 2)..(line_index * 2 + 2)]
+            .iter()
+            .map(|vertex| {
+                (
+                    stroke::to_pixels(vertex.position, self.pixel_size),
+                    vertex.color,
+                )
+            })
+            .collect()
+    }
+
+    /// Build the dashes of the one pixel lines into the mesh as line list
+    /// pairs.
+    ///
+    /// Unused pairs of the allocation are zeroed, so the first vertex is off
+    /// screen. An empty mesh leaves the one already written in place.
+    fn build_dashes(&mut self) {
+        self.mesh.clear();
+        if self.style.is_solid() {
+            return;
+        }
+        self.mesh.vertices.push(Vertex {
+            position: HIDDEN,
+            ..Vertex::default()
+        });
+        for i in 0..self.widths.len() {
+            if self.widths[i].is_none() {
+                let ends = self.ends(i);
+                stroke::dash_line_list(&mut self.mesh, &ends, false, &self.style);
+            }
+        }
+        if self.mesh.indices.is_empty() {
+            self.mesh.clear();
+            return;
+        }
+        self.mesh.scale_from(1, self.pixel_size);
+    }
+
+    /// Set the caps and dash pattern of the lines. One pixel lines are dashed
+    /// too, but have no caps. Each line starts at the beginning of the
+    /// pattern.
+    pub fn set_stroke_style(&mut self, style: StrokeStyle) {
+        self.style = style;
+        self.dirty = true;
+    }
 }
 
 impl Shape for Lines {
@@ -72,17 +204,115 @@ impl Shape for Lines {
         self.dirty = true;
     }
 
+    fn set_z_index(&mut self, z_index: i32) {
+        self.z_index = z_index;
+        self.dirty = true;
+    }
+
     fn draw(&mut self, renderer: &mut Renderer, parent_pos: Point2) {
+        // Wide and dashed lines have to be rebuilt when the window size
+        // changes.
+        let scaled = self.widths.iter().any(Option::is_some) || !self.style.is_solid();
+        if scaled && self.pixel_size != renderer.pixel_size() {
+            self.pixel_size = renderer.pixel_size();
+            self.dirty = true;
+        }
         if !self.dirty {
             return;
         }
         self.dirty = false;
+        let translation = Point3D::wgpu_vector(parent_pos + self.position);
 
-        //renderer.lines_buffer.copy_from_slice_with_position(
-        renderer.lines_buffer.write_vertices_with_translation(
-            self.buffer_handle,
-            &self.vertices,
-            Point3D::wgpu_vector(parent_pos + self.position),
+        renderer
+            .lines_buffer
+            .write_indices(self.buffer_handle, &self.line_indices())
+            .expect("Failed to write indices.");
+
+        self.mesh.clear();
+        for i in 0..self.widths.len() {
+            if let Some(width) = self.widths[i] {
+                let ends = self.ends(i);
+                stroke::stroke_with_style(
+                    &mut self.mesh,
+                    &ends,
+                    false,
+                    width.to_pixels(self.pixel_size),
+                    &self.style,
+                );
+            }
+        }
+        self.mesh.scale_from(0, self.pixel_size);
+        self.stroke.write(
+            &mut renderer.triangles_buffer,
+            &self.mesh,
+            self.z_index,
+            translation,
         );
+
+        self.build_dashes();
+        self.dashes.write(
+            &mut renderer.lines_buffer,
+            &self.mesh,
+            self.z_index,
+            translation,
+        );
+
+        renderer
+            .lines_buffer
+            .set_z_index(self.buffer_handle, self.z_index)
+            .expect("Failed to set z index.");
+
+        //renderer.lines_buffer.copy_from_slice_with_position(
+        renderer
+            .lines_buffer
+            .write_vertices_with_translation(self.buffer_handle, &self.vertices, translation)
+            .expect("Failed to write vertices.");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn wide_lines_point_at_the_hidden_vertex() {
+        let mut buffer = DualBuffer::new("Lines", 16, 16);
+        let size = Vector2::new(1.0, 1.0);
+        let mut lines = Lines::with_buffer(&mut buffer, size, size, 3);
+        assert_eq!(buffer.vertices[6].position, HIDDEN);
+        assert_eq!(buffer.indices[..6], [6; 6]);
+
+        lines.set_line_width(1, Some(LineWidth::Pixels(3.0)));
+        assert_eq!(lines.line_indices(), vec![0, 1, 6, 6, 4, 5]);
+        lines.set_width(Some(LineWidth::Pixels(3.0)));
+        assert_eq!(lines.line_indices(), vec![6; 6]);
+    }
+
+    #[test]
+    fn dashed_one_pixel_lines_are_built_on_the_cpu() {
+        let mut buffer = DualBuffer::new("Lines", 16, 16);
+        let size = Vector2::new(1.0, 1.0);
+        let pixel_size = Vector2::new(0.1, 0.1);
+        let mut lines = Lines::with_buffer(&mut buffer, pixel_size, size, 2);
+        lines.set_line_position(0, Point2::new(0.0, 0.0), Point2::new(0.5, 0.0));
+        lines.set_line_width(1, Some(LineWidth::Pixels(3.0)));
+        lines.set_stroke_style(StrokeStyle::dashed(3.0, 2.0));
+        assert_eq!(lines.line_indices(), vec![4; 4]);
+
+        // The line is 10 pixels long, so has two dashes. The wide line is
+        // dashed into triangles instead.
+        lines.build_dashes();
+        assert_eq!(lines.mesh.vertices[0].position, HIDDEN);
+        assert_eq!(lines.mesh.indices, vec![1, 2, 3, 4]);
+        let x = |i: usize| lines.mesh.vertices[i].position.x;
+        let start = Point3D::from(Point2::new(0.0, 0.0)).x;
+        assert!((x(2) - x(1) - 0.3).abs() < 1e-6);
+        assert!((x(1) - start).abs() < 1e-6);
+        assert!((x(3) - start - 0.5).abs() < 1e-6);
+
+        lines.set_stroke_style(StrokeStyle::solid());
+        lines.build_dashes();
+        assert!(lines.mesh.vertices.is_empty());
+        assert_eq!(lines.line_indices(), vec![0, 1, 4, 4]);
     }
 }
@@ -0,0 +1,175 @@
+//! Geometry built on the CPU whose size can change between frames.
+
+use crate::{AllocationHandle, Color, DualBuffer, Point3D, Vector2, Vertex};
+use cgmath::InnerSpace;
+
+/// Largest distance in pixels between a curve and the segments approximating it.
+const TOLERANCE: f32 = 0.25;
+
+/// Number of segments needed to approximate an arc of `radius` pixels turning
+/// through `sweep` radians.
+pub(crate) fn arc_segments(radius: f32, sweep: f32) -> u32 {
+    if radius <= TOLERANCE {
+        return 1;
+    }
+    let step = 2.0 * (1.0 - TOLERANCE / radius).acos();
+    ((sweep.abs() / step).ceil() as u32).max(1)
+}
+
+/// Number of segments needed to approximate a quadratic Bézier curve with
+/// control points in pixels.
+pub(crate) fn quad_segments(from: Vector2, control: Vector2, to: Vector2) -> u32 {
+    let bend = (from - control * 2.0 + to).magnitude();
+    ((bend / (4.0 * TOLERANCE)).sqrt().ceil() as u32).max(1)
+}
+
+/// Number of segments needed to approximate a cubic Bézier curve with control
+/// points in pixels.
+pub(crate) fn cubic_segments(
+    from: Vector2,
+    control1: Vector2,
+    control2: Vector2,
+    to: Vector2,
+) -> u32 {
+    let bend = (from - control1 * 2.0 + control2)
+        .magnitude()
+        .max((control1 - control2 * 2.0 + to).magnitude());
+    ((3.0 * bend / (4.0 * TOLERANCE)).sqrt().ceil() as u32).max(1)
+}
+
+/// A position far outside the window. A line list pair with both ends at a
+/// vertex here draws nothing, where a zero length line on screen might still
+/// light a pixel.
+pub(crate) const HIDDEN: Point3D = Point3D {
+    x: -1.0e6,
+    y: -1.0e6,
+    z: 0.0,
+};
+
+/// Triangles or lines with indices relative to the first vertex.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct Mesh {
+    pub vertices: Vec<Vertex>,
+    pub indices: Vec<u32>,
+}
+
+impl Mesh {
+    pub fn clear(&mut self) {
+        self.vertices.clear();
+        self.indices.clear();
+    }
+
+    pub fn push_vertex(&mut self, position: Vector2, color: Color) -> u32 {
+        self.vertices.push(Vertex {
+            position: Point3D {
+                x: position.x,
+                y: position.y,
+                z: 0.0,
+            },
+            color,
+            ..Vertex::default()
+        });
+        self.vertices.len() as u32 - 1
+    }
+
+    pub fn push_triangle(&mut self, a: u32, b: u32, c: u32) {
+        self.indices.extend_from_slice(&[a, b, c]);
+    }
+
+    /// Scale the positions of the vertices from `first` onwards, for example
+    /// from pixels to normalised device coordinates.
+    pub fn scale_from(&mut self, first: usize, scale: Vector2) {
+        for vertex in &mut self.vertices[first..] {
+            vertex.position.x *= scale.x;
+            vertex.position.y *= scale.y;
+        }
+    }
+}
+
+/// Buffer space for a [`Mesh`] that grows when the mesh outgrows it.
+///
+/// Unused indices are zeroed so they draw nothing. Line list meshes start with
+/// a vertex at [`HIDDEN`] for this to hold.
+#[derive(Debug, Default)]
+pub(crate) struct MeshAllocation {
+    handle: Option<AllocationHandle>,
+    num_vertices: u32,
+    num_indices: u32,
+}
+
+impl MeshAllocation {
+    /// Make room for at least this many vertices and indices, so meshes up to
+    /// that size can be written without reallocating.
+    pub fn reserve(&mut self, buffer: &mut DualBuffer, num_vertices: u32, num_indices: u32) {
+        if num_vertices <= self.num_vertices && num_indices <= self.num_indices {
+            return;
+        }
+        let num_vertices = num_vertices.max(self.num_vertices);
+        let num_indices = num_indices.max(self.num_indices);
+        self.free(buffer);
+        self.num_vertices = num_vertices;
+        self.num_indices = num_indices;
+        self.handle = Some(
+            buffer
+                .alloc(num_vertices, num_indices)
+                .expect("Failed to allocate buffer space."),
+        );
+    }
+
+    /// Copy `mesh` into `buffer`, reallocating if it doesn't fit.
+    pub fn write(
+        &mut self,
+        buffer: &mut DualBuffer,
+        mesh: &Mesh,
+        z_index: i32,
+        translation: Point3D,
+    ) {
+        let num_vertices = mesh.vertices.len() as u32;
+        let num_indices = mesh.indices.len() as u32;
+        if num_vertices > self.num_vertices || num_indices > self.num_indices {
+            let (old_vertices, old_indices) = (self.num_vertices, self.num_indices);
+            self.free(buffer);
+            self.num_vertices = num_vertices.max(old_vertices * 2);
+            self.num_indices = num_indices.max(old_indices * 2);
+            self.handle = Some(
+                buffer
+                    .alloc(self.num_vertices, self.num_indices)
+                    .expect("Failed to allocate buffer space."),
+            );
+        }
+
+        let handle = match self.handle {
+            Some(handle) => handle,
+            None => return,
+        };
+
+        buffer
+            .set_z_index(handle, z_index)
+            .expect("Failed to set z index.");
+        buffer
+            .write_vertices_with_translation(handle, &mesh.vertices, translation)
+            .expect("Failed to write vertices.");
+
+        let mut indices = mesh.indices.clone();
+        indices.resize(self.num_indices as usize, 0);
+        buffer
+            .write_indices(handle, &indices)
+            .expect("Failed to write indices.");
+    }
+
+    #[cfg(test)]
+    pub fn handle(&self) -> Option<AllocationHandle> {
+        self.handle
+    }
+
+    /// Release the buffer space.
+    pub fn free(&mut self, buffer: &mut DualBuffer) {
+        if let Some(handle) = self.handle.take() {
+            buffer
+                .free(handle)
+                .expect("Failed to free buffer allocation.");
+        }
+        self.num_vertices = 0;
+        self.num_indices = 0;
+    }
+}
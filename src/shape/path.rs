@@ -0,0 +1,565 @@
+use super::fill::{self, Fill};
+use super::mesh::{arc_segments, cubic_segments, quad_segments, Mesh, MeshAllocation};
+use super::stroke;
+use super::triangulate::{triangulate_nested, PolygonError};
+use crate::{Color, DrawMode, LineWidth, Point2, Point3D, Renderer, Shape, StrokeStyle, Vector2};
+use cgmath::{ElementWise, InnerSpace};
+
+#[derive(Clone, Copy, Debug)]
+enum Segment {
+    MoveTo(Vector2),
+    LineTo(Vector2),
+    QuadTo(Vector2, Vector2),
+    CubicTo(Vector2, Vector2, Vector2),
+    ArcTo { center: Vector2, sweep: f32 },
+    Close,
+}
+
+/// A series of lines and curves to draw with a [`Path`].
+///
+/// Points are relative to the position of the shape, and drawing starts there
+/// until the first `move_to`. Arcs are circular in these units, so like other
+/// shapes they stretch with the window.
+#[derive(Clone, Debug, Default)]
+pub struct PathBuilder {
+    segments: Vec<Segment>,
+}
+
+impl PathBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Start a new subpath at `to`.
+    pub fn move_to(&mut self, to: Point2) -> &mut Self {
+        self.segments.push(Segment::MoveTo(to_vector(to)));
+        self
+    }
+
+    /// Add a straight line from the current point to `to`.
+    pub fn line_to(&mut self, to: Point2) -> &mut Self {
+        self.segments.push(Segment::LineTo(to_vector(to)));
+        self
+    }
+
+    /// Add a quadratic Bézier curve from the current point to `to`, bending
+    /// towards `control`.
+    pub fn quad_to(&mut self, control: Point2, to: Point2) -> &mut Self {
+        self.segments
+            .push(Segment::QuadTo(to_vector(control), to_vector(to)));
+        self
+    }
+
+    /// Add a cubic Bézier curve from the current point to `to`, leaving
+    /// towards `control1` and arriving from the direction of `control2`.
+    pub fn cubic_to(&mut self, control1: Point2, control2: Point2, to: Point2) -> &mut Self {
+        self.segments.push(Segment::CubicTo(
+            to_vector(control1),
+            to_vector(control2),
+            to_vector(to),
+        ));
+        self
+    }
+
+    /// Add an arc around `center` from the current point, turning through
+    /// `sweep` radians. Positive sweeps turn clockwise on screen.
+    pub fn arc_to(&mut self, center: Point2, sweep: f32) -> &mut Self {
+        self.segments.push(Segment::ArcTo {
+            center: to_vector(center),
+            sweep,
+        });
+        self
+    }
+
+    /// Join the current point back to the start of the subpath. Drawing
+    /// carries on from there in a new subpath.
+    pub fn close(&mut self) -> &mut Self {
+        self.segments.push(Segment::Close);
+        self
+    }
+}
+
+fn to_vector(point: Point2) -> Vector2 {
+    Vector2::new(point.x, point.y)
+}
+
+/// A flattened subpath in pixels.
+#[derive(Debug)]
+struct SubPath {
+    points: Vec<Vector2>,
+    closed: bool,
+}
+
+/// Turn the segments into straight lines, using `to_pixels` to decide how
+/// finely to split curves.
+fn flatten(segments: &[Segment], to_pixels: impl Fn(Vector2) -> Vector2) -> Vec<SubPath> {
+    let mut subpaths = Vec::new();
+    let mut points = Vec::new();
+    let mut current = Vector2::new(0.0, 0.0);
+    let mut start = current;
+
+    for segment in segments {
+        let draws = !matches!(segment, Segment::MoveTo(_) | Segment::Close);
+        if draws && points.is_empty() {
+            points.push(to_pixels(current));
+        }
+
+        match *segment {
+            Segment::MoveTo(to) => {
+                finish(&mut subpaths, &mut points, false);
+                current = to;
+                start = to;
+            }
+            Segment::LineTo(to) => {
+                push_point(&mut points, to_pixels(to));
+                current = to;
+            }
+            Segment::QuadTo(control, to) => {
+                let (a, b, c) = (to_pixels(current), to_pixels(control), to_pixels(to));
+                let segments = quad_segments(a, b, c);
+                for i in 1..=segments {
+                    let t = i as f32 / segments as f32;
+                    let u = 1.0 - t;
+                    push_point(&mut points, a * (u * u) + b * (2.0 * u * t) + c * (t * t));
+                }
+                current = to;
+            }
+            Segment::CubicTo(control1, control2, to) => {
+                let (a, b, c, d) = (
+                    to_pixels(current),
+                    to_pixels(control1),
+                    to_pixels(control2),
+                    to_pixels(to),
+                );
+                let segments = cubic_segments(a, b, c, d);
+                for i in 1..=segments {
+                    let t = i as f32 / segments as f32;
+                    let u = 1.0 - t;
+                    push_point(
+                        &mut points,
+                        a * (u * u * u)
+                            + b * (3.0 * u * u * t)
+                            + c * (3.0 * u * t * t)
+                            + d * (t * t * t),
+                    );
+                }
+                current = to;
+            }
+            Segment::ArcTo { center, sweep } => {
+                let radius = current - center;
+                // The arc is an ellipse in pixels, so use its larger radius.
+                let pixel_radius = to_pixels(radius)
+                    .magnitude()
+                    .max(to_pixels(Vector2::new(-radius.y, radius.x)).magnitude());
+                let segments = arc_segments(pixel_radius, sweep);
+                for i in 1..=segments {
+                    let (sin, cos) = (sweep * i as f32 / segments as f32).sin_cos();
+                    current = center
+                        + Vector2::new(
+                            radius.x * cos - radius.y * sin,
+                            radius.x * sin + radius.y * cos,
+                        );
+                    push_point(&mut points, to_pixels(current));
+                }
+            }
+            Segment::Close => {
+                finish(&mut subpaths, &mut points, true);
+                current = start;
+            }
+        }
+    }
+    finish(&mut subpaths, &mut points, false);
+
+    subpaths
+}
+
+/// Add `point` unless it repeats the last point.
+fn push_point(points: &mut Vec<Vector2>, point: Vector2) {
+    if let Some(last) = points.last() {
+        if (point - *last).magnitude2() <= f32::EPSILON {
+            return;
+        }
+    }
+    points.push(point);
+}
+
+fn finish(subpaths: &mut Vec<SubPath>, points: &mut Vec<Vector2>, closed: bool) {
+    let mut points = std::mem::take(points);
+    if closed && returns_to_start(&points) {
+        points.pop();
+    }
+    if !points.is_empty() {
+        subpaths.push(SubPath { points, closed });
+    }
+}
+
+/// Whether the last of three or more points repeats the first.
+fn returns_to_start(points: &[Vector2]) -> bool {
+    points.len() > 2 && (points[0] - points[points.len() - 1]).magnitude2() <= f32::EPSILON
+}
+
+/// The rings filled for `subpaths`, with open subpaths closed and lines
+/// dropped.
+fn fill_rings(subpaths: &[SubPath]) -> Vec<&[Vector2]> {
+    subpaths
+        .iter()
+        .map(|subpath| {
+            let points = subpath.points.as_slice();
+            if returns_to_start(points) {
+                &points[..(points.len() - 1)]
+            } else {
+                points
+            }
+        })
+        .filter(|ring| ring.len() > 2)
+        .collect()
+}
+
+/// A shape made of lines, Bézier curves and arcs.
+///
+/// Curves are split into straight lines finely enough to look smooth at the
+/// current window size. Filling uses the even-odd rule, so a subpath inside
+/// another one cuts a hole in it. Open subpaths are closed for filling.
+#[derive(Debug)]
+pub struct Path {
+    position: Vector2,
+    /// Bounding box of the path, used to scale it on resize.
+    size: Vector2,
+    /// Scale from the units of the path to the current size.
+    scale: Vector2,
+    color: Color,
+    fill: Option<Fill>,
+    segments: Vec<Segment>,
+    draw_mode: DrawMode,
+    stroke_width: LineWidth,
+    stroke_color: Color,
+    stroke_style: StrokeStyle,
+    fill_error: Option<PolygonError>,
+    /// Pixel size the path was flattened for.
+    pixel_size: Vector2,
+    /// The segments split into straight lines, kept until the segments or
+    /// their size in pixels change.
+    subpaths: Vec<SubPath>,
+    /// Triangle indices filling the flattened subpaths, built on the first
+    /// draw that fills them.
+    triangulation: Option<Result<Vec<u32>, PolygonError>>,
+    /// Whether the subpaths have to be flattened again.
+    flatten: bool,
+    mesh: Mesh,
+    allocation: MeshAllocation,
+    z_index: i32,
+    dirty: bool,
+}
+
+impl Path {
+    pub fn new(renderer: &mut Renderer, path: &PathBuilder) -> Self {
+        Self::with_pixel_size(renderer.pixel_size(), path)
+    }
+
+    fn with_pixel_size(pixel_size: Vector2, path: &PathBuilder) -> Self {
+        let mut shape = Self {
+            position: Vector2::new(0.0, 0.0),
+            size: Vector2::new(0.0, 0.0),
+            scale: Vector2::new(1.0, 1.0),
+            color: Color::default(),
+            fill: None,
+            segments: Vec::new(),
+            draw_mode: DrawMode::default(),
+            stroke_width: LineWidth::Pixels(1.0),
+            stroke_color: Color::BLACK,
+            stroke_style: StrokeStyle::default(),
+            fill_error: None,
+            pixel_size,
+            subpaths: Vec::new(),
+            triangulation: None,
+            flatten: true,
+            mesh: Mesh::default(),
+            allocation: MeshAllocation::default(),
+            z_index: 0,
+            dirty: true,
+        };
+        shape.set_path(path);
+        shape
+    }
+
+    /// Release the buffer space used by this shape.
+    pub fn free(mut self, renderer: &mut Renderer) {
+        self.allocation.free(&mut renderer.triangles_buffer);
+    }
+
+    /// Replace the lines and curves, at their original scale.
+    pub fn set_path(&mut self, path: &PathBuilder) {
+        self.segments = path.segments.clone();
+        self.scale = Vector2::new(1.0, 1.0);
+
+        let (min, max) = flatten(&self.segments, |point| self.to_pixels(point))
+            .iter()
+            .flat_map(|subpath| subpath.points.iter())
+            .fold(
+                (
+                    Vector2::new(f32::MAX, f32::MAX),
+                    Vector2::new(f32::MIN, f32::MIN),
+                ),
+                |(min, max), point| {
+                    (
+                        Vector2::new(min.x.min(point.x), min.y.min(point.y)),
+                        Vector2::new(max.x.max(point.x), max.y.max(point.y)),
+                    )
+                },
+            );
+        self.size = if min.x <= max.x {
+            (max - min).mul_element_wise(self.pixel_size) / 2.0
+        } else {
+            Vector2::new(0.0, 0.0)
+        };
+        self.flatten = true;
+        self.dirty = true;
+    }
+
+    /// Why the path couldn't be filled the last time it was drawn, for
+    /// example because it crosses itself. Such paths are left unfilled.
+    pub fn fill_error(&self) -> Option<PolygonError> {
+        self.fill_error
+    }
+
+    /// Colour the inside with a solid colour or a gradient. Setting the
+    /// colour replaces the fill.
+    pub fn set_fill(&mut self, fill: Fill) {
+        self.fill = Some(fill);
+        self.dirty = true;
+    }
+
+    /// Choose whether to fill the shape, outline it, or both.
+    pub fn set_draw_mode(&mut self, draw_mode: DrawMode) {
+        self.draw_mode = draw_mode;
+        self.dirty = true;
+    }
+
+    /// Set the width and colour of the outline, which is centred on the path.
+    pub fn set_stroke(&mut self, width: LineWidth, color: Color) {
+        self.stroke_width = width;
+        self.stroke_color = color;
+        self.dirty = true;
+    }
+
+    /// Set the caps, joins and dash pattern of the outline.
+    pub fn set_stroke_style(&mut self, style: StrokeStyle) {
+        self.stroke_style = style;
+        self.dirty = true;
+    }
+
+    /// Convert a point of the path to pixels.
+    fn to_pixels(&self, point: Vector2) -> Vector2 {
+        stroke::to_pixels(
+            Point3D::from(point.mul_element_wise(self.scale)),
+            self.pixel_size,
+        )
+    }
+
+    /// Fill and stroke the flattened subpaths, flattening and triangulating
+    /// them first if they changed.
+    fn build_mesh(&mut self) {
+        if self.flatten {
+            self.flatten = false;
+            self.subpaths = flatten(&self.segments, |point| self.to_pixels(point));
+            self.triangulation = None;
+        }
+
+        self.mesh.clear();
+        if self.draw_mode.fills() {
+            let rings = fill_rings(&self.subpaths);
+            let triangulation = self
+                .triangulation
+                .get_or_insert_with(|| triangulate_nested(&rings));
+            match triangulation {
+                Ok(indices) => {
+                    for point in rings.iter().copied().flatten() {
+                        self.mesh.push_vertex(*point, self.color);
+                    }
+                    self.mesh.indices.extend_from_slice(indices);
+                    if let Some(fill) = &self.fill {
+                        fill::apply(&mut self.mesh, 0, fill);
+                    }
+                    self.fill_error = None;
+                }
+                Err(error) => self.fill_error = Some(*error),
+            }
+        }
+        if self.draw_mode.strokes() {
+            let width = self.stroke_width.to_pixels(self.pixel_size);
+            for subpath in &self.subpaths {
+                let points: Vec<_> = subpath
+                    .points
+                    .iter()
+                    .map(|&point| (point, self.stroke_color))
+                    .collect();
+                stroke::stroke_with_style(
+                    &mut self.mesh,
+                    &points,
+                    subpath.closed,
+                    width,
+                    &self.stroke_style,
+                );
+            }
+        }
+        self.mesh.scale_from(0, self.pixel_size);
+    }
+}
+
+impl Shape for Path {
+    fn set_position(&mut self, position: Vector2) {
+        self.position = position;
+        self.dirty = true;
+    }
+
+    fn resize(&mut self, size: Vector2) {
+        // A straight path has no height or width to scale from.
+        if self.size.x > 0.0 {
+            self.scale.x *= size.x / self.size.x;
+        }
+        if self.size.y > 0.0 {
+            self.scale.y *= size.y / self.size.y;
+        }
+        self.size = size;
+        self.flatten = true;
+        self.dirty = true;
+    }
+
+    fn set_color(&mut self, color: Color) {
+        self.color = color;
+        self.fill = None;
+        self.dirty = true;
+    }
+
+    fn set_z_index(&mut self, z_index: i32) {
+        self.z_index = z_index;
+        self.dirty = true;
+    }
+
+    fn draw(&mut self, renderer: &mut Renderer, parent_pos: Point2) {
+        // Curves are split for the window size, so they have to be split
+        // again when it changes.
+        if self.pixel_size != renderer.pixel_size() {
+            self.pixel_size = renderer.pixel_size();
+            self.flatten = true;
+            self.dirty = true;
+        }
+        if !self.dirty {
+            return;
+        }
+        self.dirty = false;
+
+        self.build_mesh();
+        self.allocation.write(
+            &mut renderer.triangles_buffer,
+            &self.mesh,
+            self.z_index,
+            Point3D::from(parent_pos + self.position),
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Shortest distance from `point` to the segment `a b`.
+    fn distance_to_segment(point: Vector2, a: Vector2, b: Vector2) -> f32 {
+        let along = b - a;
+        let t = ((point - a).dot(along) / along.magnitude2()).clamp(0.0, 1.0);
+        (a + along * t - point).magnitude()
+    }
+
+    /// Total area covered by the triangles of a mesh.
+    fn area(mesh: &Mesh) -> f32 {
+        mesh.indices
+            .chunks(3)
+            .map(|t| {
+                let [a, b, c] = [t[0], t[1], t[2]].map(|i| mesh.vertices[i as usize].position);
+                ((b.x - a.x) * (c.y - a.y) - (b.y - a.y) * (c.x - a.x)).abs() / 2.0
+            })
+            .sum()
+    }
+
+    fn square(path: &mut PathBuilder, x: f32, y: f32, size: f32) {
+        path.move_to(Point2::new(x, y))
+            .line_to(Point2::new(x + size, y))
+            .line_to(Point2::new(x + size, y + size))
+            .line_to(Point2::new(x, y + size))
+            .close();
+    }
+
+    #[test]
+    fn quads_are_flattened_within_tolerance() {
+        let (a, b, c) = (
+            Vector2::new(0.0, 0.0),
+            Vector2::new(50.0, 100.0),
+            Vector2::new(100.0, 0.0),
+        );
+        let subpaths = flatten(&[Segment::QuadTo(b, c)], |point| point);
+        assert_eq!(subpaths.len(), 1);
+        let points = &subpaths[0].points;
+        assert_eq!(points[0], a);
+        assert_eq!(points[points.len() - 1], c);
+
+        for i in 0..=1000 {
+            let t = i as f32 / 1000.0;
+            let u = 1.0 - t;
+            let on_curve = a * (u * u) + b * (2.0 * u * t) + c * (t * t);
+            let distance = points
+                .windows(2)
+                .map(|pair| distance_to_segment(on_curve, pair[0], pair[1]))
+                .fold(f32::INFINITY, f32::min);
+            assert!(
+                distance <= 0.25 + 1e-3,
+                "{} pixels off at t = {}",
+                distance,
+                t
+            );
+        }
+    }
+
+    #[test]
+    fn inner_ring_cuts_a_hole() {
+        let mut builder = PathBuilder::new();
+        square(&mut builder, 0.0, 0.0, 0.5);
+        square(&mut builder, 0.1, 0.1, 0.2);
+        let mut path = Path::with_pixel_size(Vector2::new(0.01, 0.01), &builder);
+        path.build_mesh();
+
+        assert_eq!(path.fill_error(), None);
+        // Normalised coordinates are twice the path units.
+        let expected = 4.0 * (0.5 * 0.5 - 0.2 * 0.2);
+        assert!((area(&path.mesh) - expected).abs() < 1e-4);
+    }
+
+    #[test]
+    fn self_crossing_path_is_left_unfilled() {
+        let mut builder = PathBuilder::new();
+        builder
+            .move_to(Point2::new(0.0, 0.0))
+            .line_to(Point2::new(0.5, 0.5))
+            .line_to(Point2::new(0.5, 0.0))
+            .line_to(Point2::new(0.0, 0.5))
+            .close();
+        let mut path = Path::with_pixel_size(Vector2::new(0.01, 0.01), &builder);
+        path.build_mesh();
+
+        assert_eq!(path.fill_error(), Some(PolygonError::SelfIntersecting));
+        assert!(path.mesh.indices.is_empty());
+    }
+
+    #[test]
+    fn moving_keeps_the_triangulation() {
+        let mut builder = PathBuilder::new();
+        square(&mut builder, 0.0, 0.0, 0.5);
+        let mut path = Path::with_pixel_size(Vector2::new(0.01, 0.01), &builder);
+        path.build_mesh();
+        assert!(path.triangulation.is_some());
+
+        path.set_position(Vector2::new(0.2, 0.2));
+        assert!(!path.flatten);
+        path.resize(Vector2::new(1.0, 1.0));
+        assert!(path.flatten);
+    }
+}
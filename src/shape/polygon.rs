@@ -1,4 +1,10 @@
-use crate::{Color, Point2, Point3D, Renderer, Shape, Vector2, Vertex};
+use super::fill::{self, Fill};
+use super::mesh::{Mesh, MeshAllocation};
+use super::{feather, stroke};
+use crate::{
+    Antialiasing, Color, DrawMode, LineWidth, Point2, Point3D, Renderer, Shape, StrokeStyle,
+    Vector2, Vertex,
+};
 use std::f32::consts::PI;
 
 #[derive(Debug)]
@@ -7,45 +13,88 @@ pub struct Polygon {
     size: Vector2,
     point_count: u8,
     vertices: Vec<Vertex>,
-    buffer_handle: usize,
+    fill: Option<Fill>,
+    draw_mode: DrawMode,
+    stroke_width: LineWidth,
+    stroke_color: Color,
+    stroke_style: StrokeStyle,
+    feathered: bool,
+    /// Pixel size the fringe and outline were built for.
+    pixel_size: Vector2,
+    mesh: Mesh,
+    allocation: MeshAllocation,
+    z_index: i32,
     dirty: bool,
 }
 
 impl Polygon {
     pub fn new(renderer: &mut Renderer, size: Vector2, point_count: u8) -> Self {
-        let vertices = Self::gen_vertices(size, point_count);
-        let mut indices = Vec::with_capacity(point_count as usize * 3);
-        for i in 1..(vertices.len() as u32) {
-            if i == 1 {
-                indices.push(0);
-                indices.push(vertices.len() as u32 - 1);
-                indices.push(1);
-            } else {
-                indices.push(0);
-                indices.push(i - 1);
-                indices.push(i);
-            }
-        }
-
-        let buffer_handle = renderer
-            .triangles_buffer
-            .alloc(vertices.len() as u32, indices.len() as u32)
-            .unwrap();
-
-        renderer
-            .triangles_buffer
-            .write_indices(buffer_handle, &indices);
-
         Self {
             position: Vector2::new(0.0, 0.0),
             size,
             point_count,
-            vertices,
-            buffer_handle,
+            vertices: Self::gen_vertices(size, point_count),
+            fill: None,
+            draw_mode: DrawMode::default(),
+            stroke_width: LineWidth::Pixels(1.0),
+            stroke_color: Color::BLACK,
+            stroke_style: StrokeStyle::default(),
+            feathered: renderer.antialiasing() == Antialiasing::Feathered,
+            pixel_size: renderer.pixel_size(),
+            mesh: Mesh::default(),
+            allocation: MeshAllocation::default(),
+            z_index: 0,
             dirty: true,
         }
     }
 
+    /// Release the buffer space used by this shape.
+    pub fn free(mut self, renderer: &mut Renderer) {
+        self.allocation.free(&mut renderer.triangles_buffer);
+    }
+
+    /// Colour the inside with a solid colour or a gradient. Setting the
+    /// colour replaces the fill.
+    pub fn set_fill(&mut self, fill: Fill) {
+        self.fill = Some(fill);
+        self.dirty = true;
+    }
+
+    /// Choose whether to fill the shape, outline it, or both.
+    pub fn set_draw_mode(&mut self, draw_mode: DrawMode) {
+        self.draw_mode = draw_mode;
+        self.dirty = true;
+    }
+
+    /// Set the width and colour of the outline, which is centred on the edge.
+    pub fn set_stroke(&mut self, width: LineWidth, color: Color) {
+        self.stroke_width = width;
+        self.stroke_color = color;
+        self.dirty = true;
+    }
+
+    /// Set the joins and dash pattern of the outline.
+    pub fn set_stroke_style(&mut self, style: StrokeStyle) {
+        self.stroke_style = style;
+        self.dirty = true;
+    }
+
+    /// Indices of the vertices on the edge, leaving out the centre.
+    fn outline(point_count: u8) -> Vec<u32> {
+        (1..=point_count as u32).collect()
+    }
+
+    /// Triangles fanning out from the centre.
+    fn fill_indices(point_count: u8) -> Vec<u32> {
+        let point_count = point_count as u32;
+        let mut indices = Vec::with_capacity(point_count as usize * 3);
+        for i in 1..=point_count {
+            let prev = if i == 1 { point_count } else { i - 1 };
+            indices.extend_from_slice(&[0, prev, i]);
+        }
+        indices
+    }
+
     fn gen_vertices(size: Vector2, point_count: u8) -> Vec<Vertex> {
         let vertex_count = point_count as usize + 1;
         let mut vertices = Vec::with_capacity(vertex_count);
@@ -60,6 +109,7 @@ impl Polygon {
                     z: 0.0,
                 },
                 color: Color::default(),
+                ..Vertex::default()
             });
         }
 
@@ -80,21 +130,62 @@ impl Shape for Polygon {
     }
 
     fn set_color(&mut self, color: Color) {
+        self.fill = None;
         for vertex in &mut self.vertices {
             vertex.color = color;
         }
         self.dirty = true;
     }
 
+    fn set_z_index(&mut self, z_index: i32) {
+        self.z_index = z_index;
+        self.dirty = true;
+    }
+
     fn draw(&mut self, renderer: &mut Renderer, parent_pos: Point2) {
+        // The fringe and outline have to be rebuilt when the window size
+        // changes.
+        let pixel_dependent = self.feathered || self.draw_mode.strokes();
+        if pixel_dependent && self.pixel_size != renderer.pixel_size() {
+            self.pixel_size = renderer.pixel_size();
+            self.dirty = true;
+        }
         if !self.dirty {
             return;
         }
         self.dirty = false;
 
-        renderer.triangles_buffer.write_vertices_with_translation(
-            self.buffer_handle,
-            &self.vertices,
+        let outline = Self::outline(self.point_count);
+        self.mesh.clear();
+        self.mesh.vertices.extend_from_slice(&self.vertices);
+        if self.draw_mode.fills() {
+            self.mesh.indices = Self::fill_indices(self.point_count);
+            // The fringe follows the edges as the fill split them.
+            let fringe = match &self.fill {
+                Some(fill) => fill::apply(&mut self.mesh, 0, fill).outline(&outline),
+                None => outline.clone(),
+            };
+            if self.feathered {
+                let first = self.mesh.vertices.len() as u32;
+                feather::push_indices(&mut self.mesh.indices, &fringe, first);
+                feather::push_vertices(&mut self.mesh.vertices, &fringe, self.pixel_size);
+            }
+        }
+        if self.draw_mode.strokes() {
+            stroke::stroke_outline(
+                &mut self.mesh,
+                &outline,
+                self.stroke_color,
+                self.stroke_width,
+                &self.stroke_style,
+                self.pixel_size,
+            );
+        }
+
+        self.allocation.write(
+            &mut renderer.triangles_buffer,
+            &self.mesh,
+            self.z_index,
             //(parent_pos + self.position + self.size / 2.0).into(),
             (parent_pos + self.position).into(),
         );
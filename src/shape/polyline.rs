@@ -0,0 +1,460 @@
+use super::mesh::{Mesh, MeshAllocation, HIDDEN};
+use super::stroke::{self, LineWidth, StrokeStyle};
+use crate::{
+    AllocationHandle, Color, DualBuffer, Point2, Point3D, Renderer, Shape, Vector2, Vertex,
+};
+use cgmath::ElementWise;
+
+/// A connected line through a series of points.
+///
+/// Points are kept in a ring buffer: once it is full, pushing a point drops
+/// the oldest one. Each segment shares its vertices with its neighbours, and
+/// pushing a point onto a solid one pixel line only uploads that point and the
+/// two segments touching it.
+#[derive(Debug)]
+pub struct Polyline {
+    position: Vector2,
+    size: Vector2,
+    color: Color,
+    /// Ring buffer slots, one vertex each.
+    points: Vec<Vertex>,
+    /// Slot of the oldest point.
+    head: usize,
+    len: usize,
+    closed: bool,
+    width: Option<LineWidth>,
+    style: StrokeStyle,
+    buffer_handle: AllocationHandle,
+    /// Wide lines, tessellated into the triangles buffer.
+    stroke: MeshAllocation,
+    /// Dashes of a one pixel line, in the lines buffer.
+    dashes: MeshAllocation,
+    mesh: Mesh,
+    pixel_size: Vector2,
+    z_index: i32,
+    /// Slots written since the last draw.
+    changed: Vec<usize>,
+    dirty: bool,
+}
+
+impl Polyline {
+    /// Create an empty line that holds up to `capacity` points.
+    pub fn new(renderer: &mut Renderer, size: Vector2, capacity: usize) -> Self {
+        let pixel_size = renderer.pixel_size();
+        Self::with_buffer(&mut renderer.lines_buffer, pixel_size, size, capacity)
+    }
+
+    fn with_buffer(
+        buffer: &mut DualBuffer,
+        pixel_size: Vector2,
+        size: Vector2,
+        capacity: usize,
+    ) -> Self {
+        let capacity = capacity.max(1);
+        let buffer_handle = Self::alloc(buffer, capacity);
+
+        Self {
+            position: Vector2::new(0.0, 0.0),
+            size,
+            color: Color::default(),
+            points: vec![Vertex::default(); capacity],
+            head: 0,
+            len: 0,
+            closed: false,
+            width: None,
+            style: StrokeStyle::default(),
+            buffer_handle,
+            stroke: MeshAllocation::default(),
+            dashes: MeshAllocation::default(),
+            mesh: Mesh::default(),
+            pixel_size,
+            z_index: 0,
+            changed: Vec::new(),
+            dirty: true,
+        }
+    }
+
+    /// Release the buffer space used by this shape.
+    pub fn free(mut self, renderer: &mut Renderer) {
+        self.stroke.free(&mut renderer.triangles_buffer);
+        self.dashes.free(&mut renderer.lines_buffer);
+        renderer
+            .lines_buffer
+            .free(self.buffer_handle)
+            .expect("Failed to free buffer allocation.");
+    }
+
+    /// Allocate a slot per point plus one off screen vertex for segments
+    /// that aren't drawn.
+    fn alloc(buffer: &mut DualBuffer, capacity: usize) -> AllocationHandle {
+        let handle = buffer
+            .alloc(capacity as u32 + 1, capacity as u32 * 2)
+            .expect("Failed to allocate buffer space.");
+        let hidden = Vertex {
+            position: HIDDEN,
+            ..Vertex::default()
+        };
+        buffer
+            .write_vertices_at(handle, capacity as u32, &[hidden], Point3D::default())
+            .expect("Failed to write vertices.");
+        handle
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.points.len()
+    }
+
+    /// Add a point to the end of the line, dropping the oldest point if the
+    /// line is full.
+    pub fn push_point(&mut self, point: Point2) {
+        let capacity = self.capacity();
+        let slot = if self.len < capacity {
+            self.len += 1;
+            (self.head + self.len - 1) % capacity
+        } else {
+            let slot = self.head;
+            self.head = (self.head + 1) % capacity;
+            slot
+        };
+        self.points[slot] = Vertex {
+            position: point.into(),
+            color: self.color,
+            ..Vertex::default()
+        };
+
+        if self.rebuilt() || self.changed.len() >= capacity {
+            self.dirty = true;
+        } else if !self.dirty {
+            self.changed.push(slot);
+        }
+    }
+
+    /// Replace all points, growing the ring buffer if they don't fit.
+    pub fn set_points(&mut self, renderer: &mut Renderer, points: &[Point2]) {
+        if points.len() > self.capacity() {
+            renderer
+                .lines_buffer
+                .free(self.buffer_handle)
+                .expect("Failed to free buffer allocation.");
+            self.buffer_handle = Self::alloc(&mut renderer.lines_buffer, points.len());
+            self.points = vec![Vertex::default(); points.len()];
+        }
+
+        for (slot, point) in self.points.iter_mut().zip(points) {
+            *slot = Vertex {
+                position: (*point).into(),
+                color: self.color,
+                ..Vertex::default()
+            };
+        }
+        self.head = 0;
+        self.len = points.len();
+        self.dirty = true;
+    }
+
+    /// Remove all points.
+    pub fn clear(&mut self) {
+        self.head = 0;
+        self.len = 0;
+        self.dirty = true;
+    }
+
+    /// Join the last point back to the first.
+    pub fn set_closed(&mut self, closed: bool) {
+        self.closed = closed;
+        self.dirty = true;
+    }
+
+    /// Set the width of the line. `None` draws a one pixel line.
+    pub fn set_width(&mut self, width: Option<LineWidth>) {
+        self.width = width;
+        self.dirty = true;
+    }
+
+    /// Set the caps, joins and dash pattern of the line. A one pixel line is
+    /// dashed too, but has no caps or joins.
+    pub fn set_stroke_style(&mut self, style: StrokeStyle) {
+        self.style = style;
+        self.dirty = true;
+    }
+
+    /// Whether the whole line is rebuilt on the CPU when it changes, rather
+    /// than drawn straight from the points.
+    fn rebuilt(&self) -> bool {
+        self.width.is_some() || !self.style.is_solid()
+    }
+
+    /// Points in order from oldest to newest.
+    fn ordered(&self) -> impl Iterator<Item = &Vertex> {
+        let capacity = self.capacity();
+        (0..self.len).map(move |i| &self.points[(self.head + i) % capacity])
+    }
+
+    /// Indices of the segment starting at `slot`, which are the off screen
+    /// vertex twice if there is no such segment.
+    fn segment(&self, slot: usize) -> [u32; 2] {
+        let capacity = self.capacity();
+        let hidden = [capacity as u32; 2];
+        let offset = (slot + capacity - self.head) % capacity;
+        let newest = (self.head + self.len + capacity - 1) % capacity;
+        if self.rebuilt() || offset >= self.len {
+            hidden
+        } else if slot == newest {
+            if self.closed && self.len > 2 {
+                [slot as u32, self.head as u32]
+            } else {
+                hidden
+            }
+        } else {
+            [slot as u32, ((slot + 1) % capacity) as u32]
+        }
+    }
+
+    /// Points in order from oldest to newest, in pixels.
+    fn pixels(&self) -> Vec<(Vector2, Color)> {
+        self.ordered()
+            .map(|vertex| {
+                (
+                    stroke::to_pixels(vertex.position, self.pixel_size),
+                    vertex.color,
+                )
+            })
+            .collect()
+    }
+
+    /// Build the dashes of a one pixel line into the mesh as line list
+    /// pairs.
+    ///
+    /// Unused pairs of the allocation are zeroed, so the first vertex is off
+    /// screen. An empty mesh leaves the one already written in place.
+    fn build_dashes(&mut self) {
+        self.mesh.clear();
+        if self.width.is_some() || self.style.is_solid() || self.len < 2 {
+            return;
+        }
+        self.mesh.vertices.push(Vertex {
+            position: HIDDEN,
+            ..Vertex::default()
+        });
+        let points = self.pixels();
+        stroke::dash_line_list(&mut self.mesh, &points, self.closed, &self.style);
+        if self.mesh.indices.is_empty() {
+            self.mesh.clear();
+            return;
+        }
+        self.mesh.scale_from(1, self.pixel_size);
+    }
+
+    /// Rewrite only the pushed points and the segments on either side.
+    fn write_changed(&mut self, buffer: &mut DualBuffer, translation: Point3D) {
+        let capacity = self.capacity();
+        for slot in std::mem::take(&mut self.changed) {
+            buffer
+                .write_vertices_at(
+                    self.buffer_handle,
+                    slot as u32,
+                    &self.points[slot..=slot],
+                    translation,
+                )
+                .expect("Failed to write vertices.");
+            for segment in &[(slot + capacity - 1) % capacity, slot] {
+                buffer
+                    .write_indices_at(
+                        self.buffer_handle,
+                        *segment as u32 * 2,
+                        &self.segment(*segment),
+                    )
+                    .expect("Failed to write indices.");
+            }
+        }
+    }
+
+    /// Rewrite every point and segment of the one pixel wide line.
+    fn write_line(&mut self, buffer: &mut DualBuffer, translation: Point3D) {
+        self.dirty = false;
+        self.changed.clear();
+
+        buffer
+            .set_z_index(self.buffer_handle, self.z_index)
+            .expect("Failed to set z index.");
+        buffer
+            .write_vertices_with_translation(self.buffer_handle, &self.points, translation)
+            .expect("Failed to write vertices.");
+        let mut indices = Vec::with_capacity(self.capacity() * 2);
+        for slot in 0..self.capacity() {
+            indices.extend_from_slice(&self.segment(slot));
+        }
+        buffer
+            .write_indices(self.buffer_handle, &indices)
+            .expect("Failed to write indices.");
+    }
+}
+
+impl Shape for Polyline {
+    fn set_position(&mut self, position: Vector2) {
+        self.position = position;
+        self.dirty = true;
+    }
+
+    fn resize(&mut self, size: Vector2) {
+        let scale = size.div_element_wise(self.size);
+        self.size = size;
+        for vertex in &mut self.points {
+            vertex.position.x *= scale.x;
+            vertex.position.y *= scale.y;
+        }
+        self.dirty = true;
+    }
+
+    fn set_color(&mut self, color: Color) {
+        self.color = color;
+        for vertex in &mut self.points {
+            vertex.color = color;
+        }
+        self.dirty = true;
+    }
+
+    fn set_z_index(&mut self, z_index: i32) {
+        self.z_index = z_index;
+        self.dirty = true;
+    }
+
+    fn draw(&mut self, renderer: &mut Renderer, parent_pos: Point2) {
+        // Wide and dashed lines have to be rebuilt when the window size
+        // changes.
+        if self.rebuilt() && self.pixel_size != renderer.pixel_size() {
+            self.pixel_size = renderer.pixel_size();
+            self.dirty = true;
+        }
+        let translation = Point3D::wgpu_vector(parent_pos + self.position);
+
+        if !self.dirty {
+            self.write_changed(&mut renderer.lines_buffer, translation);
+            return;
+        }
+        self.write_line(&mut renderer.lines_buffer, translation);
+
+        self.mesh.clear();
+        if let Some(width) = self.width {
+            let points = self.pixels();
+            stroke::stroke_with_style(
+                &mut self.mesh,
+                &points,
+                self.closed,
+                width.to_pixels(self.pixel_size),
+                &self.style,
+            );
+            self.mesh.scale_from(0, self.pixel_size);
+        }
+        self.stroke.write(
+            &mut renderer.triangles_buffer,
+            &self.mesh,
+            self.z_index,
+            translation,
+        );
+
+        self.build_dashes();
+        self.dashes.write(
+            &mut renderer.lines_buffer,
+            &self.mesh,
+            self.z_index,
+            translation,
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn line(buffer: &mut DualBuffer, capacity: usize, xs: &[f32]) -> Polyline {
+        let size = Vector2::new(1.0, 1.0);
+        let mut line = Polyline::with_buffer(buffer, size, size, capacity);
+        for &x in xs {
+            line.push_point(Point2::new(x, 0.0));
+        }
+        line.write_line(buffer, Point3D::default());
+        line
+    }
+
+    fn xs(line: &Polyline) -> Vec<f32> {
+        line.ordered().map(|vertex| vertex.position.x).collect()
+    }
+
+    #[test]
+    fn pushing_past_capacity_drops_the_oldest_point() {
+        let mut buffer = DualBuffer::new("Lines", 8, 16);
+        let mut line = line(&mut buffer, 3, &[0.0, 0.1, 0.2]);
+        line.push_point(Point2::new(0.3, 0.0));
+
+        assert_eq!(line.len(), 3);
+        let expected: Vec<f32> = [0.1, 0.2, 0.3]
+            .iter()
+            .map(|&x| Point3D::from(Point2::new(x, 0.0)).x)
+            .collect();
+        assert_eq!(xs(&line), expected);
+    }
+
+    #[test]
+    fn pushing_past_capacity_writes_the_touched_segments() {
+        let mut buffer = DualBuffer::new("Lines", 8, 16);
+        let mut line = line(&mut buffer, 3, &[0.0, 0.1, 0.2]);
+        assert_eq!(buffer.indices[..6], [0, 1, 1, 2, 3, 3]);
+        assert_eq!(buffer.vertices[3].position, HIDDEN);
+
+        // The new point takes the oldest slot. The previous newest point now
+        // joins it, and the new point ends the line.
+        line.push_point(Point2::new(0.3, 0.0));
+        line.write_changed(&mut buffer, Point3D::default());
+        assert_eq!(
+            buffer.vertices[0].position,
+            Point3D::from(Point2::new(0.3, 0.0))
+        );
+        assert_eq!(buffer.indices[..6], [3, 3, 1, 2, 2, 0]);
+
+        // Writing every segment gives the same indices.
+        let incremental = buffer.indices[..6].to_vec();
+        line.write_line(&mut buffer, Point3D::default());
+        assert_eq!(buffer.indices[..6], incremental[..]);
+    }
+
+    #[test]
+    fn closed_lines_join_the_newest_point_to_the_oldest() {
+        let mut buffer = DualBuffer::new("Lines", 8, 16);
+        let mut line = line(&mut buffer, 3, &[0.0, 0.1, 0.2]);
+        line.set_closed(true);
+        line.write_line(&mut buffer, Point3D::default());
+        assert_eq!(buffer.indices[..6], [0, 1, 1, 2, 2, 0]);
+
+        line.push_point(Point2::new(0.3, 0.0));
+        line.write_changed(&mut buffer, Point3D::default());
+        assert_eq!(buffer.indices[..6], [0, 1, 1, 2, 2, 0]);
+    }
+
+    #[test]
+    fn dashed_lines_are_built_on_the_cpu() {
+        let mut buffer = DualBuffer::new("Lines", 8, 16);
+        let mut line = line(&mut buffer, 3, &[0.0, 0.1, 0.2]);
+        line.pixel_size = Vector2::new(0.02, 0.02);
+        line.set_stroke_style(StrokeStyle::dashed(4.0, 6.0));
+        line.write_line(&mut buffer, Point3D::default());
+        assert_eq!(buffer.indices[..6], [3; 6]);
+
+        // Pushing a point rebuilds the whole line rather than writing it.
+        line.push_point(Point2::new(0.3, 0.0));
+        assert!(line.dirty);
+
+        // The line is 20 pixels long, so has two dashes.
+        line.build_dashes();
+        assert_eq!(line.mesh.vertices[0].position, HIDDEN);
+        assert_eq!(line.mesh.indices, vec![1, 2, 3, 4]);
+    }
+}
@@ -1,11 +1,68 @@
-use crate::{Color, Point2, Point3D, Renderer, Shape, Vector2, Vertex};
+use super::fill::{self, Fill};
+use super::mesh::{arc_segments, Mesh, MeshAllocation};
+use super::{feather, stroke};
+use crate::{
+    Antialiasing, Color, DrawMode, LineWidth, Point2, Point3D, Renderer, Shape, StrokeStyle,
+    Vector2, Vertex,
+};
+use cgmath::InnerSpace;
+use std::f32::consts::FRAC_PI_2;
+
+/// Corners in drawing order.
+const OUTLINE: [u32; 4] = [0, 1, 2, 3];
+
+/// Radii of the corners of a [`Rectangle`] in pixels.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct CornerRadii {
+    pub top_left: f32,
+    pub top_right: f32,
+    pub bottom_right: f32,
+    pub bottom_left: f32,
+}
+
+impl CornerRadii {
+    /// The same radius for every corner.
+    pub fn all(radius: f32) -> Self {
+        Self {
+            top_left: radius,
+            top_right: radius,
+            bottom_right: radius,
+            bottom_left: radius,
+        }
+    }
+
+    /// Radii in the order of the rectangle's corners.
+    fn in_drawing_order(self) -> [f32; 4] {
+        [
+            self.top_left,
+            self.bottom_left,
+            self.bottom_right,
+            self.top_right,
+        ]
+    }
+
+    fn is_square(self) -> bool {
+        self.in_drawing_order().iter().all(|&radius| radius <= 0.0)
+    }
+}
 
 #[derive(Debug)]
 pub struct Rectangle {
     position: Vector2,
     size: Vector2,
     vertices: [Vertex; 4],
-    buffer_handle: usize,
+    corner_radii: CornerRadii,
+    fill: Option<Fill>,
+    draw_mode: DrawMode,
+    stroke_width: LineWidth,
+    stroke_color: Color,
+    stroke_style: StrokeStyle,
+    feathered: bool,
+    /// Pixel size the fringe and outline were built for.
+    pixel_size: Vector2,
+    mesh: Mesh,
+    allocation: MeshAllocation,
+    z_index: i32,
     dirty: bool,
 }
 
@@ -17,23 +74,66 @@ impl Rectangle {
         vertices[2].position = size_3d;
         vertices[3].position.x = size_3d.x;
         vertices[0].color = Color::RED;
-        vertices[1].color = Color::GREEN;
+        vertices[1].color = Color::LIME;
         vertices[2].color = Color::BLUE;
-        vertices[3].color = Color::GREEN;
-
-        let buffer_handle = renderer.triangles_buffer.alloc(4, 6).unwrap();
-        renderer
-            .triangles_buffer
-            .write_indices(buffer_handle, &[0, 1, 2, 0, 2, 3]);
+        vertices[3].color = Color::LIME;
 
         Self {
             position: Vector2::new(0.0, 0.0),
             size,
             vertices,
-            buffer_handle,
+            corner_radii: CornerRadii::default(),
+            fill: None,
+            draw_mode: DrawMode::default(),
+            stroke_width: LineWidth::Pixels(1.0),
+            stroke_color: Color::BLACK,
+            stroke_style: StrokeStyle::default(),
+            feathered: renderer.antialiasing() == Antialiasing::Feathered,
+            pixel_size: renderer.pixel_size(),
+            mesh: Mesh::default(),
+            allocation: MeshAllocation::default(),
+            z_index: 0,
             dirty: true,
         }
     }
+
+    /// Release the buffer space used by this shape.
+    pub fn free(mut self, renderer: &mut Renderer) {
+        self.allocation.free(&mut renderer.triangles_buffer);
+    }
+
+    /// Round the corners. Radii too large for the rectangle are shrunk
+    /// together until neighbouring corners meet.
+    pub fn set_corner_radii(&mut self, corner_radii: CornerRadii) {
+        self.corner_radii = corner_radii;
+        self.dirty = true;
+    }
+
+    /// Colour the inside with a solid colour or a gradient. Setting the
+    /// colour replaces the fill.
+    pub fn set_fill(&mut self, fill: Fill) {
+        self.fill = Some(fill);
+        self.dirty = true;
+    }
+
+    /// Choose whether to fill the shape, outline it, or both.
+    pub fn set_draw_mode(&mut self, draw_mode: DrawMode) {
+        self.draw_mode = draw_mode;
+        self.dirty = true;
+    }
+
+    /// Set the width and colour of the outline, which is centred on the edge.
+    pub fn set_stroke(&mut self, width: LineWidth, color: Color) {
+        self.stroke_width = width;
+        self.stroke_color = color;
+        self.dirty = true;
+    }
+
+    /// Set the joins and dash pattern of the outline.
+    pub fn set_stroke_style(&mut self, style: StrokeStyle) {
+        self.stroke_style = style;
+        self.dirty = true;
+    }
 }
 
 impl Shape for Rectangle {
@@ -52,22 +152,216 @@ impl Shape for Rectangle {
     }
 
     fn set_color(&mut self, color: Color) {
+        self.fill = None;
         for vertex in &mut self.vertices {
             vertex.color = color;
         }
         self.dirty = true;
     }
 
+    fn set_z_index(&mut self, z_index: i32) {
+        self.z_index = z_index;
+        self.dirty = true;
+    }
+
     fn draw(&mut self, renderer: &mut Renderer, parent_pos: Point2) {
+        // The corners, fringe and outline have to be rebuilt when the window
+        // size changes.
+        let pixel_dependent =
+            self.feathered || self.draw_mode.strokes() || !self.corner_radii.is_square();
+        if pixel_dependent && self.pixel_size != renderer.pixel_size() {
+            self.pixel_size = renderer.pixel_size();
+            self.dirty = true;
+        }
         if !self.dirty {
             return;
         }
         self.dirty = false;
 
-        renderer.triangles_buffer.write_vertices_with_translation(
-            self.buffer_handle,
-            &self.vertices,
+        self.mesh.clear();
+        let outline = if self.corner_radii.is_square() {
+            self.mesh.vertices.extend_from_slice(&self.vertices);
+            OUTLINE.to_vec()
+        } else {
+            push_rounded_outline(
+                &mut self.mesh,
+                &self.vertices,
+                self.corner_radii,
+                self.pixel_size,
+            )
+        };
+        if self.draw_mode.fills() {
+            // The outline is convex, so a fan covers it.
+            for i in 1..(outline.len() - 1) {
+                self.mesh
+                    .push_triangle(outline[0], outline[i], outline[i + 1]);
+            }
+            // The fringe follows the edges as the fill split them.
+            let fringe = match &self.fill {
+                Some(fill) => fill::apply(&mut self.mesh, 0, fill).outline(&outline),
+                None => outline.clone(),
+            };
+            if self.feathered {
+                let first = self.mesh.vertices.len() as u32;
+                feather::push_indices(&mut self.mesh.indices, &fringe, first);
+                feather::push_vertices(&mut self.mesh.vertices, &fringe, self.pixel_size);
+            }
+        }
+        if self.draw_mode.strokes() {
+            stroke::stroke_outline(
+                &mut self.mesh,
+                &outline,
+                self.stroke_color,
+                self.stroke_width,
+                &self.stroke_style,
+                self.pixel_size,
+            );
+        }
+
+        self.allocation.write(
+            &mut renderer.triangles_buffer,
+            &self.mesh,
+            self.z_index,
             Point3D::from(parent_pos + self.position),
         );
     }
 }
+
+/// Append the outline of a rectangle with rounded corners and return the
+/// indices of its vertices in drawing order.
+///
+/// Each arc takes the colour of its corner.
+fn push_rounded_outline(
+    mesh: &mut Mesh,
+    corners: &[Vertex; 4],
+    corner_radii: CornerRadii,
+    pixel_size: Vector2,
+) -> Vec<u32> {
+    let points: Vec<Vector2> = corners
+        .iter()
+        .map(|corner| stroke::to_pixels(corner.position, pixel_size))
+        .collect();
+    let width = (points[3].x - points[0].x).abs();
+    let height = (points[1].y - points[0].y).abs();
+
+    // Shrink all radii by the same factor if two corners on a side overlap.
+    let radii = corner_radii.in_drawing_order();
+    let sides = [
+        (radii[0] + radii[1], height),
+        (radii[1] + radii[2], width),
+        (radii[2] + radii[3], height),
+        (radii[3] + radii[0], width),
+    ];
+    let scale = sides.iter().fold(1.0f32, |scale, &(sum, side)| {
+        if sum > side {
+            scale.min(side / sum)
+        } else {
+            scale
+        }
+    });
+
+    // Where each arc starts, relative to its centre, going counter-clockwise
+    // from the top left corner.
+    let starts = [
+        Vector2::new(0.0, 1.0),
+        Vector2::new(-1.0, 0.0),
+        Vector2::new(0.0, -1.0),
+        Vector2::new(1.0, 0.0),
+    ];
+
+    let mut around: Vec<(Vector2, Color)> = Vec::new();
+    for i in 0..4 {
+        let radius = radii[i].max(0.0) * scale;
+        let start = starts[i];
+        let center = points[i] - (start + Vector2::new(-start.y, start.x)) * radius;
+        let segments = if radius > 0.0 {
+            arc_segments(radius, FRAC_PI_2)
+        } else {
+            0
+        };
+        for step in 0..=segments {
+            let angle = FRAC_PI_2 * step as f32 / segments.max(1) as f32;
+            let (sin, cos) = angle.sin_cos();
+            let point = center
+                + Vector2::new(start.x * cos - start.y * sin, start.x * sin + start.y * cos)
+                    * radius;
+            // Corners whose arcs meet would repeat a point.
+            if let Some(&(last, _)) = around.last() {
+                if (point - last).magnitude2() <= f32::EPSILON {
+                    continue;
+                }
+            }
+            around.push((point, corners[i].color));
+        }
+    }
+    if around.len() > 1 && (around[0].0 - around[around.len() - 1].0).magnitude2() <= f32::EPSILON {
+        around.pop();
+    }
+
+    let first = mesh.vertices.len();
+    let outline = around
+        .iter()
+        .map(|&(point, color)| mesh.push_vertex(point, color))
+        .collect();
+    mesh.scale_from(first, pixel_size);
+    outline
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Corners of a rectangle in pixels, in drawing order.
+    fn corners(width: f32, height: f32) -> [Vertex; 4] {
+        let mut corners = [Vertex::origin(); 4];
+        corners[1].position.y = -height;
+        corners[2].position = Point3D {
+            x: width,
+            y: -height,
+            z: 0.0,
+        };
+        corners[3].position.x = width;
+        corners
+    }
+
+    #[test]
+    fn zero_radii_give_a_plain_quad() {
+        let corners = corners(100.0, 50.0);
+        let mut mesh = Mesh::default();
+        let outline = push_rounded_outline(
+            &mut mesh,
+            &corners,
+            CornerRadii::default(),
+            Vector2::new(1.0, 1.0),
+        );
+        assert_eq!(outline, OUTLINE.to_vec());
+        for (vertex, corner) in mesh.vertices.iter().zip(&corners) {
+            assert_eq!(vertex.position, corner.position);
+        }
+    }
+
+    #[test]
+    fn large_radii_shrink_to_fit() {
+        let mut mesh = Mesh::default();
+        let outline = push_rounded_outline(
+            &mut mesh,
+            &corners(100.0, 50.0),
+            CornerRadii::all(100.0),
+            Vector2::new(1.0, 1.0),
+        );
+
+        // Every radius shrinks to half the height, so the arcs on the short
+        // sides meet and share a point.
+        let segments = arc_segments(25.0, FRAC_PI_2) as usize;
+        assert_eq!(outline.len(), 4 * (segments + 1) - 2);
+        for vertex in &mesh.vertices {
+            let position = vertex.position;
+            assert!(position.x >= -1e-4 && position.x <= 100.0 + 1e-4);
+            assert!(position.y <= 1e-4 && position.y >= -50.0 - 1e-4);
+        }
+        let on_left = |vertex: &&Vertex| vertex.position.x.abs() < 1e-4;
+        let left: Vec<_> = mesh.vertices.iter().filter(on_left).collect();
+        assert_eq!(left.len(), 1);
+        assert!((left[0].position.y + 25.0).abs() < 1e-4);
+    }
+}
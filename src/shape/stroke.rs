@@ -0,0 +1,941 @@
+//! Tessellation of wide lines into triangles, and dashing of one pixel lines.
+
+use super::mesh::{arc_segments, Mesh};
+use crate::{Color, Point3D, Vector2};
+use cgmath::InnerSpace;
+use std::f32::consts::PI;
+
+/// Longest miter, as a multiple of the line width, before a miter join is
+/// drawn as a bevel.
+const MITER_LIMIT: f32 = 4.0;
+
+/// Shortest dash pattern in pixels. Shorter patterns can't be told apart from
+/// a solid line, so they are drawn as one.
+const MIN_DASH_PATTERN: f32 = 1.0;
+
+/// Shortest dash of a one pixel line. Shorter dashes, such as dots, might not
+/// light any pixels.
+const MIN_LINE_DASH: f32 = 1.0;
+
+/// Most dashes drawn along one line. Longer lines are drawn solid rather than
+/// building an unbounded mesh.
+const MAX_DASHES: f32 = 10_000.0;
+
+/// Shape drawn at the open ends of a line.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum LineCap {
+    /// End exactly at the end point.
+    #[default]
+    Butt,
+    /// Extend past the end point by half the line width.
+    Square,
+    /// A half circle around the end point.
+    Round,
+}
+
+/// Shape drawn where two segments of a line meet.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum LineJoin {
+    /// Extend the outer edges until they meet. Very sharp corners fall back
+    /// to a bevel.
+    #[default]
+    Miter,
+    /// Cut the corner off with a straight edge.
+    Bevel,
+    /// Round the corner with an arc.
+    Round,
+}
+
+/// How the path of a line is turned into a stroke.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct StrokeStyle {
+    pub cap: LineCap,
+    pub join: LineJoin,
+    /// Lengths in pixels of alternating dashes and gaps, starting with a dash.
+    /// An odd number of lengths is repeated to make it even. Empty for a solid
+    /// line.
+    pub dashes: Vec<f32>,
+    /// Distance in pixels into the dash pattern at which the line starts.
+    pub dash_offset: f32,
+}
+
+impl StrokeStyle {
+    pub fn solid() -> Self {
+        Self::default()
+    }
+
+    pub fn dashed(dash: f32, gap: f32) -> Self {
+        Self {
+            dashes: vec![dash, gap],
+            ..Self::default()
+        }
+    }
+
+    /// Round dots `spacing` pixels apart.
+    pub fn dotted(spacing: f32) -> Self {
+        Self {
+            cap: LineCap::Round,
+            dashes: vec![0.0, spacing],
+            ..Self::default()
+        }
+    }
+
+    pub fn dash_dot(dash: f32, gap: f32) -> Self {
+        Self {
+            dashes: vec![dash, gap, 0.0, gap],
+            ..Self::default()
+        }
+    }
+
+    pub fn with_cap(mut self, cap: LineCap) -> Self {
+        self.cap = cap;
+        self
+    }
+
+    pub fn with_join(mut self, join: LineJoin) -> Self {
+        self.join = join;
+        self
+    }
+
+    pub fn with_dash_offset(mut self, dash_offset: f32) -> Self {
+        self.dash_offset = dash_offset;
+        self
+    }
+
+    pub(crate) fn is_solid(&self) -> bool {
+        self.dashes
+            .iter()
+            .any(|length| !length.is_finite() || *length < 0.0)
+            || self.dashes.iter().sum::<f32>() < MIN_DASH_PATTERN
+    }
+}
+
+/// Width of a line.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum LineWidth {
+    /// Device pixels.
+    Pixels(f32),
+    /// Fraction of the smaller window dimension, so lines scale with the
+    /// window.
+    Normalised(f32),
+}
+
+impl LineWidth {
+    /// Width in pixels, given the size of a pixel in normalised device
+    /// coordinates.
+    pub fn to_pixels(self, pixel_size: Vector2) -> f32 {
+        match self {
+            LineWidth::Pixels(width) => width,
+            LineWidth::Normalised(width) => width * (2.0 / pixel_size.x).min(2.0 / pixel_size.y),
+        }
+    }
+}
+
+/// Append triangles covering a line `width` pixels wide through `points`.
+///
+/// Positions are in pixels. Each point carries the colour of the geometry
+/// around it. A closed line joins its last point back to the first and has
+/// no caps and is drawn as a single ring of triangles, so translucent strokes
+/// don't overlap themselves.
+pub(crate) fn stroke(
+    mesh: &mut Mesh,
+    points: &[(Vector2, Color)],
+    closed: bool,
+    width: f32,
+    cap: LineCap,
+    join: LineJoin,
+) {
+    let half = width / 2.0;
+    if half <= 0.0 {
+        return;
+    }
+
+    // Repeated points have no direction to stroke along.
+    let mut unique: Vec<(Vector2, Color)> = Vec::with_capacity(points.len());
+    for &point in points {
+        if let Some(last) = unique.last() {
+            if (point.0 - last.0).magnitude2() <= f32::EPSILON {
+                continue;
+            }
+        }
+        unique.push(point);
+    }
+    if closed
+        && unique.len() > 2
+        && (unique[0].0 - unique[unique.len() - 1].0).magnitude2() <= f32::EPSILON
+    {
+        unique.pop();
+    }
+    let points = unique;
+    let closed = closed && points.len() > 2;
+    let count = points.len();
+
+    if count == 1 {
+        push_dot(mesh, points[0].0, points[0].1, half, cap);
+        return;
+    }
+    if closed {
+        push_ring(mesh, &points, half, join);
+        return;
+    }
+
+    for i in 0..count.saturating_sub(1) {
+        let (start, start_color) = points[i];
+        let (end, end_color) = points[i + 1];
+        let normal = perp(direction(start, end)) * half;
+
+        let a = mesh.push_vertex(start + normal, start_color);
+        let b = mesh.push_vertex(start - normal, start_color);
+        let c = mesh.push_vertex(end - normal, end_color);
+        let d = mesh.push_vertex(end + normal, end_color);
+        mesh.push_triangle(a, b, c);
+        mesh.push_triangle(a, c, d);
+    }
+
+    for i in 1..count.saturating_sub(1) {
+        let (prev, next) = (points[i - 1].0, points[i + 1].0);
+        push_join(mesh, prev, points[i].0, next, points[i].1, half, join);
+    }
+
+    if count > 1 {
+        let (first, first_color) = points[0];
+        let (last, last_color) = points[count - 1];
+        push_cap(
+            mesh,
+            first,
+            direction(points[1].0, first),
+            first_color,
+            half,
+            cap,
+        );
+        push_cap(
+            mesh,
+            last,
+            direction(points[count - 2].0, last),
+            last_color,
+            half,
+            cap,
+        );
+    }
+}
+
+/// Like [`stroke`], but splits the line into the dashes of `style` first.
+pub(crate) fn stroke_with_style(
+    mesh: &mut Mesh,
+    points: &[(Vector2, Color)],
+    closed: bool,
+    width: f32,
+    style: &StrokeStyle,
+) {
+    if style.is_solid() || too_many_dashes(length(points, closed), &style.dashes) {
+        stroke(mesh, points, closed, width, style.cap, style.join);
+        return;
+    }
+    for dash in dash(points, closed, &style.dashes, style.dash_offset) {
+        stroke(mesh, &dash, false, width, style.cap, style.join);
+    }
+}
+
+/// Append the one pixel line through `points` to `mesh` as line list pairs,
+/// split into the dashes of `style`.
+///
+/// Positions are in pixels. Caps and joins don't apply, and dashes shorter
+/// than a pixel, such as dots, are lengthened to a pixel at the expense of the
+/// gap after them.
+pub(crate) fn dash_line_list(
+    mesh: &mut Mesh,
+    points: &[(Vector2, Color)],
+    closed: bool,
+    style: &StrokeStyle,
+) {
+    let mut pattern = style.dashes.clone();
+    if pattern.len() % 2 == 1 {
+        pattern.extend_from_slice(&style.dashes);
+    }
+    for pair in pattern.chunks_mut(2) {
+        let extra = (MIN_LINE_DASH - pair[0]).max(0.0);
+        pair[0] += extra;
+        pair[1] = (pair[1] - extra).max(0.0);
+    }
+
+    let dashes = if style.is_solid() || too_many_dashes(length(points, closed), &pattern) {
+        let mut path = points.to_vec();
+        if closed && points.len() > 2 {
+            path.push(points[0]);
+        }
+        vec![path]
+    } else {
+        dash(points, closed, &pattern, style.dash_offset)
+    };
+    for dash in dashes {
+        let first = mesh.vertices.len() as u32;
+        for &(position, color) in &dash {
+            mesh.push_vertex(position, color);
+        }
+        for i in 1..(dash.len() as u32) {
+            mesh.indices.extend_from_slice(&[first + i - 1, first + i]);
+        }
+    }
+}
+
+/// Most vertices and indices [`stroke_with_style`] adds for a line through at
+/// most `points` points and at most `length` pixels long.
+///
+/// Shapes whose outline changes size use this to size their buffer space once.
+pub(crate) fn max_size(
+    points: usize,
+    closed: bool,
+    length: f32,
+    width: f32,
+    style: &StrokeStyle,
+) -> (u32, u32) {
+    if width <= 0.0 || points == 0 {
+        return (0, 0);
+    }
+    // Round caps and joins never turn through more than half a turn.
+    let round = arc_segments(width / 2.0, PI);
+    let (join_vertices, join_indices) = match style.join {
+        LineJoin::Miter | LineJoin::Bevel => (4, 6),
+        LineJoin::Round => (round + 2, 3 * round),
+    };
+    let (cap_vertices, cap_indices) = match style.cap {
+        LineCap::Butt => (0, 0),
+        LineCap::Square => (4, 6),
+        LineCap::Round => (round + 2, 3 * round),
+    };
+
+    // Each dash starts and ends with a new point and has two caps. A dot
+    // takes no more than its two caps would.
+    let lines = if style.is_solid() || too_many_dashes(length, &style.dashes) {
+        if closed {
+            0
+        } else {
+            1
+        }
+    } else {
+        // An odd pattern is repeated to make it even.
+        let repeats = if style.dashes.len() % 2 == 1 { 2 } else { 1 };
+        let per_pattern = (style.dashes.len() * repeats / 2) as u32;
+        let total = style.dashes.iter().sum::<f32>() * repeats as f32;
+        ((length / total).ceil() as u32 + 1) * per_pattern
+    };
+    // A closed line visits its first point again. Every point can have a
+    // quad before it and a join, which also covers the corners of a ring.
+    let points = points as u32 + 1 + 2 * lines;
+    (
+        points * (4 + join_vertices) + 2 * lines * cap_vertices,
+        points * (6 + join_indices) + 2 * lines * cap_indices,
+    )
+}
+
+/// Length in pixels of the line through `points`.
+fn length(points: &[(Vector2, Color)], closed: bool) -> f32 {
+    let mut length: f32 = points
+        .windows(2)
+        .map(|pair| (pair[1].0 - pair[0].0).magnitude())
+        .sum();
+    if closed && points.len() > 2 {
+        length += (points[0].0 - points[points.len() - 1].0).magnitude();
+    }
+    length
+}
+
+/// Whether dashing a line `length` pixels long would give more than
+/// [`MAX_DASHES`] dashes.
+fn too_many_dashes(length: f32, dashes: &[f32]) -> bool {
+    length / dashes.iter().sum::<f32>() > MAX_DASHES
+}
+
+/// Stroke the closed loop through the mesh vertices at `outline`.
+///
+/// Vertex positions are in normalised device coordinates, as are the added
+/// vertices.
+pub(crate) fn stroke_outline(
+    mesh: &mut Mesh,
+    outline: &[u32],
+    color: Color,
+    width: LineWidth,
+    style: &StrokeStyle,
+    pixel_size: Vector2,
+) {
+    let points: Vec<_> = outline
+        .iter()
+        .map(|&i| {
+            (
+                to_pixels(mesh.vertices[i as usize].position, pixel_size),
+                color,
+            )
+        })
+        .collect();
+    let first = mesh.vertices.len();
+    stroke_with_style(mesh, &points, true, width.to_pixels(pixel_size), style);
+    mesh.scale_from(first, pixel_size);
+}
+
+/// Split the line through `points` into the dashes of a pattern of
+/// alternating dash and gap lengths, starting `offset` into the pattern.
+///
+/// Each dash is an open line. Colours are interpolated where a dash starts or
+/// ends part way along a segment. A closed line that ends part way through a
+/// dash carries it on into the first dash, so there is no break at the seam.
+pub(crate) fn dash(
+    points: &[(Vector2, Color)],
+    closed: bool,
+    dashes: &[f32],
+    offset: f32,
+) -> Vec<Vec<(Vector2, Color)>> {
+    let mut pattern = dashes.to_vec();
+    if pattern.len() % 2 == 1 {
+        pattern.extend_from_slice(dashes);
+    }
+    let total: f32 = pattern.iter().sum();
+    if points.is_empty() || total <= 0.0 {
+        return Vec::new();
+    }
+
+    // Find where in the pattern the line starts.
+    let mut index = 0;
+    let mut remaining = pattern[0];
+    let mut skip = offset.rem_euclid(total);
+    while skip > 0.0 {
+        if skip >= remaining {
+            skip -= remaining;
+            index = (index + 1) % pattern.len();
+            remaining = pattern[index];
+        } else {
+            remaining -= skip;
+            skip = 0.0;
+        }
+    }
+
+    let closed = closed && points.len() > 2;
+    let mut path = points.to_vec();
+    if closed {
+        path.push(points[0]);
+    }
+
+    let mut result = Vec::new();
+    let mut current = Vec::new();
+    let starts_in_dash = index % 2 == 0;
+    if starts_in_dash {
+        current.push(path[0]);
+    }
+    for pair in path.windows(2) {
+        let ((start, start_color), (end, end_color)) = (pair[0], pair[1]);
+        let length = (end - start).magnitude();
+        let mut position = 0.0;
+        while length - position > remaining {
+            position += remaining;
+            let t = position / length;
+            let point = (start + (end - start) * t, start_color.lerp(end_color, t));
+            current.push(point);
+            if index % 2 == 0 {
+                result.push(std::mem::take(&mut current));
+            }
+            index = (index + 1) % pattern.len();
+            remaining = pattern[index];
+        }
+        remaining -= length - position;
+        if index % 2 == 0 {
+            current.push((end, end_color));
+        }
+    }
+    if index % 2 == 0 && !current.is_empty() {
+        if closed && starts_in_dash && !result.is_empty() {
+            // The first dash starts at the seam too, so join the two.
+            let first = result.remove(0);
+            current.extend(first.into_iter().skip(1));
+        }
+        result.push(current);
+    }
+
+    result
+}
+
+/// Convert a position in normalised device coordinates to pixels.
+pub(crate) fn to_pixels(position: Point3D, pixel_size: Vector2) -> Vector2 {
+    Vector2::new(position.x / pixel_size.x, position.y / pixel_size.y)
+}
+
+fn direction(from: Vector2, to: Vector2) -> Vector2 {
+    (to - from).normalize()
+}
+
+/// `vector` turned a quarter turn counter-clockwise.
+fn perp(vector: Vector2) -> Vector2 {
+    Vector2::new(-vector.y, vector.x)
+}
+
+/// Stroke a closed line of at least three distinct points.
+///
+/// Each corner gets one vertex on the inside of the turn and one or more on
+/// the outside, and neighbouring corners are joined by a single quad.
+fn push_ring(mesh: &mut Mesh, points: &[(Vector2, Color)], half: f32, join: LineJoin) {
+    let count = points.len();
+    // Vertices on the left and right of the line at each corner, in order.
+    let mut sides: Vec<(Vec<u32>, Vec<u32>)> = Vec::with_capacity(count);
+
+    for i in 0..count {
+        let prev = points[(i + count - 1) % count].0;
+        let (point, color) = points[i];
+        let next = points[(i + 1) % count].0;
+        let before = direction(prev, point);
+        let after = direction(point, next);
+        let cross = before.x * after.y - before.y * after.x;
+        let straight = cross.abs() <= f32::EPSILON && before.dot(after) > 0.0;
+
+        // The outside of the turn is on the right for left turns.
+        let outer_side = if cross > 0.0 { -1.0 } else { 1.0 };
+        let n0 = perp(before) * outer_side;
+        let n1 = perp(after) * outer_side;
+        let (miter, cos) = match (n0 + n1).magnitude2() {
+            length if length > f32::EPSILON => {
+                let miter = (n0 + n1).normalize();
+                (miter, miter.dot(n0))
+            }
+            _ => (n0, 1.0),
+        };
+        let miter_offset = miter * (half / cos.max(1.0 / MITER_LIMIT));
+
+        let inner = mesh.push_vertex(point - miter_offset, color);
+        let outer = if straight || (join == LineJoin::Miter && cos >= 1.0 / MITER_LIMIT) {
+            vec![mesh.push_vertex(point + miter_offset, color)]
+        } else if join == LineJoin::Round {
+            let sweep = (n0.x * n1.y - n0.y * n1.x).atan2(n0.dot(n1));
+            push_arc(mesh, point, n0 * half, sweep, color)
+        } else {
+            vec![
+                mesh.push_vertex(point + n0 * half, color),
+                mesh.push_vertex(point + n1 * half, color),
+            ]
+        };
+        for pair in outer.windows(2) {
+            mesh.push_triangle(inner, pair[0], pair[1]);
+        }
+
+        if outer_side > 0.0 {
+            sides.push((outer, vec![inner]));
+        } else {
+            sides.push((vec![inner], outer));
+        }
+    }
+
+    for i in 0..count {
+        let (left, right) = &sides[i];
+        let (next_left, next_right) = &sides[(i + 1) % count];
+        let (a, b) = (left[left.len() - 1], right[right.len() - 1]);
+        let (c, d) = (next_right[0], next_left[0]);
+        mesh.push_triangle(a, b, c);
+        mesh.push_triangle(a, c, d);
+    }
+}
+
+/// Fill the gap on the outside of the turn at `point`.
+fn push_join(
+    mesh: &mut Mesh,
+    prev: Vector2,
+    point: Vector2,
+    next: Vector2,
+    color: Color,
+    half: f32,
+    join: LineJoin,
+) {
+    let before = direction(prev, point);
+    let after = direction(point, next);
+    let cross = before.x * after.y - before.y * after.x;
+    if cross.abs() <= f32::EPSILON && before.dot(after) > 0.0 {
+        return;
+    }
+
+    let side = if cross > 0.0 { -1.0 } else { 1.0 };
+    let n0 = perp(before) * side;
+    let n1 = perp(after) * side;
+    let center = mesh.push_vertex(point, color);
+
+    if join == LineJoin::Round {
+        let sweep = (n0.x * n1.y - n0.y * n1.x).atan2(n0.dot(n1));
+        push_fan(mesh, center, point, n0 * half, sweep, color);
+        return;
+    }
+
+    let a = mesh.push_vertex(point + n0 * half, color);
+    let b = mesh.push_vertex(point + n1 * half, color);
+    let miter = n0 + n1;
+    if join == LineJoin::Miter && miter.magnitude2() > f32::EPSILON {
+        let miter = miter.normalize();
+        let cos = miter.dot(n0);
+        if cos >= 1.0 / MITER_LIMIT {
+            let tip = mesh.push_vertex(point + miter * (half / cos), color);
+            mesh.push_triangle(center, a, tip);
+            mesh.push_triangle(center, tip, b);
+            return;
+        }
+    }
+    mesh.push_triangle(center, a, b);
+}
+
+/// Add a cap at `point`, where the line leaves in `direction`.
+fn push_cap(
+    mesh: &mut Mesh,
+    point: Vector2,
+    direction: Vector2,
+    color: Color,
+    half: f32,
+    cap: LineCap,
+) {
+    let normal = perp(direction) * half;
+    match cap {
+        LineCap::Butt => {}
+        LineCap::Square => {
+            let extension = direction * half;
+            let a = mesh.push_vertex(point + normal, color);
+            let b = mesh.push_vertex(point - normal, color);
+            let c = mesh.push_vertex(point - normal + extension, color);
+            let d = mesh.push_vertex(point + normal + extension, color);
+            mesh.push_triangle(a, b, c);
+            mesh.push_triangle(a, c, d);
+        }
+        LineCap::Round => {
+            let center = mesh.push_vertex(point, color);
+            push_fan(mesh, center, point, normal, -PI, color);
+        }
+    }
+}
+
+/// Draw a line with no length as its two caps put together.
+fn push_dot(mesh: &mut Mesh, point: Vector2, color: Color, half: f32, cap: LineCap) {
+    match cap {
+        LineCap::Butt => {}
+        LineCap::Square => {
+            let a = mesh.push_vertex(point + Vector2::new(-half, -half), color);
+            let b = mesh.push_vertex(point + Vector2::new(half, -half), color);
+            let c = mesh.push_vertex(point + Vector2::new(half, half), color);
+            let d = mesh.push_vertex(point + Vector2::new(-half, half), color);
+            mesh.push_triangle(a, b, c);
+            mesh.push_triangle(a, c, d);
+        }
+        LineCap::Round => {
+            let center = mesh.push_vertex(point, color);
+            push_fan(
+                mesh,
+                center,
+                point,
+                Vector2::new(half, 0.0),
+                2.0 * PI,
+                color,
+            );
+        }
+    }
+}
+
+/// Append a fan of triangles around the vertex `center` at `point`, starting at
+/// `point + start` and turning through `sweep` radians.
+pub(crate) fn push_fan(
+    mesh: &mut Mesh,
+    center: u32,
+    point: Vector2,
+    start: Vector2,
+    sweep: f32,
+    color: Color,
+) {
+    let arc = push_arc(mesh, point, start, sweep, color);
+    for pair in arc.windows(2) {
+        mesh.push_triangle(center, pair[0], pair[1]);
+    }
+}
+
+/// Append vertices along an arc around `point`, starting at `point + start`
+/// and turning through `sweep` radians.
+pub(crate) fn push_arc(
+    mesh: &mut Mesh,
+    point: Vector2,
+    start: Vector2,
+    sweep: f32,
+    color: Color,
+) -> Vec<u32> {
+    let radius = start.magnitude();
+    let segments = arc_segments(radius, sweep);
+    let start_angle = start.y.atan2(start.x);
+
+    (0..=segments)
+        .map(|i| {
+            let angle = start_angle + sweep * i as f32 / segments as f32;
+            mesh.push_vertex(
+                point + Vector2::new(angle.cos(), angle.sin()) * radius,
+                color,
+            )
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn line(points: &[(f32, f32)]) -> Vec<(Vector2, Color)> {
+        points
+            .iter()
+            .map(|&(x, y)| (Vector2::new(x, y), Color::WHITE))
+            .collect()
+    }
+
+    fn positions(dashes: &[Vec<(Vector2, Color)>]) -> Vec<Vec<(f32, f32)>> {
+        dashes
+            .iter()
+            .map(|dash| dash.iter().map(|(p, _)| (p.x, p.y)).collect())
+            .collect()
+    }
+
+    #[test]
+    fn dashes_follow_the_pattern() {
+        let dashes = dash(&line(&[(0.0, 0.0), (30.0, 0.0)]), false, &[10.0, 5.0], 0.0);
+        assert_eq!(
+            positions(&dashes),
+            vec![
+                vec![(0.0, 0.0), (10.0, 0.0)],
+                vec![(15.0, 0.0), (25.0, 0.0)],
+            ]
+        );
+    }
+
+    #[test]
+    fn offset_shifts_and_wraps_the_pattern() {
+        let points = line(&[(0.0, 0.0), (30.0, 0.0)]);
+        let expected = vec![
+            vec![(3.0, 0.0), (13.0, 0.0)],
+            vec![(18.0, 0.0), (28.0, 0.0)],
+        ];
+        assert_eq!(
+            positions(&dash(&points, false, &[10.0, 5.0], 12.0)),
+            expected
+        );
+        assert_eq!(
+            positions(&dash(&points, false, &[10.0, 5.0], -3.0)),
+            expected
+        );
+    }
+
+    #[test]
+    fn dashes_keep_corners() {
+        let points = line(&[(0.0, 0.0), (10.0, 0.0), (10.0, 10.0)]);
+        let dashes = dash(&points, false, &[15.0, 100.0], 0.0);
+        assert_eq!(
+            positions(&dashes),
+            vec![vec![(0.0, 0.0), (10.0, 0.0), (10.0, 5.0)]]
+        );
+    }
+
+    #[test]
+    fn closed_lines_dash_the_closing_segment() {
+        let points = line(&[(0.0, 0.0), (10.0, 0.0), (10.0, 10.0), (0.0, 10.0)]);
+        let dashes = dash(&points, true, &[5.0], 0.0);
+        assert_eq!(dashes.len(), 4);
+        assert_eq!(positions(&dashes)[3], vec![(0.0, 10.0), (0.0, 5.0)]);
+    }
+
+    #[test]
+    fn closed_lines_continue_dashes_across_the_seam() {
+        let points = line(&[(0.0, 0.0), (10.0, 0.0), (10.0, 10.0), (0.0, 10.0)]);
+        let dashes = dash(&points, true, &[15.0, 10.0], 0.0);
+        assert_eq!(
+            positions(&dashes),
+            vec![vec![
+                (5.0, 10.0),
+                (0.0, 10.0),
+                (0.0, 0.0),
+                (10.0, 0.0),
+                (10.0, 5.0)
+            ]]
+        );
+    }
+
+    #[test]
+    fn tiny_dash_patterns_are_drawn_solid() {
+        let points = line(&[(0.0, 0.0), (1000.0, 0.0)]);
+        for style in &[StrokeStyle::dotted(0.0001), StrokeStyle::dashed(1e-6, 1e-6)] {
+            let mut solid = Mesh::default();
+            let solid_style = StrokeStyle::solid().with_cap(style.cap);
+            stroke_with_style(&mut solid, &points, false, 2.0, &solid_style);
+
+            let mut mesh = Mesh::default();
+            stroke_with_style(&mut mesh, &points, false, 2.0, style);
+            assert_eq!(mesh.indices.len(), solid.indices.len());
+        }
+    }
+
+    #[test]
+    fn very_long_dashed_lines_are_drawn_solid() {
+        let points = line(&[(0.0, 0.0), (1e8, 0.0)]);
+        let mut mesh = Mesh::default();
+        stroke_with_style(
+            &mut mesh,
+            &points,
+            false,
+            2.0,
+            &StrokeStyle::dashed(1.0, 1.0),
+        );
+        assert_eq!(mesh.indices.len(), 6);
+    }
+
+    #[test]
+    fn zero_length_dashes_become_dots() {
+        let points = line(&[(0.0, 0.0), (12.0, 0.0)]);
+        let mut mesh = Mesh::default();
+        stroke_with_style(&mut mesh, &points, false, 2.0, &StrokeStyle::dotted(5.0));
+
+        // Dots at 0, 5 and 10, each a full circle.
+        let centers = mesh
+            .indices
+            .chunks(3)
+            .map(|triangle| triangle[0])
+            .collect::<std::collections::BTreeSet<_>>();
+        assert_eq!(centers.len(), 3);
+    }
+
+    #[test]
+    fn butt_segment_is_a_quad() {
+        let mut mesh = Mesh::default();
+        let points = line(&[(0.0, 0.0), (10.0, 0.0)]);
+        stroke(
+            &mut mesh,
+            &points,
+            false,
+            2.0,
+            LineCap::Butt,
+            LineJoin::Miter,
+        );
+
+        assert_eq!(mesh.vertices.len(), 4);
+        assert_eq!(mesh.indices.len(), 6);
+        for vertex in &mesh.vertices {
+            assert!((vertex.position.y.abs() - 1.0).abs() < 1e-6);
+        }
+    }
+
+    #[test]
+    fn square_caps_extend_the_line() {
+        let mut mesh = Mesh::default();
+        let points = line(&[(0.0, 0.0), (10.0, 0.0)]);
+        stroke(
+            &mut mesh,
+            &points,
+            false,
+            2.0,
+            LineCap::Square,
+            LineJoin::Miter,
+        );
+
+        let xs = mesh.vertices.iter().map(|vertex| vertex.position.x);
+        let min = xs.clone().fold(f32::MAX, f32::min);
+        let max = xs.fold(f32::MIN, f32::max);
+        assert_eq!((min, max), (-1.0, 11.0));
+    }
+
+    #[test]
+    fn closed_lines_are_rings_without_overlap() {
+        let points = line(&[(0.0, 0.0), (10.0, 0.0), (10.0, 10.0), (0.0, 10.0)]);
+        for join in &[LineJoin::Miter, LineJoin::Bevel, LineJoin::Round] {
+            let mut mesh = Mesh::default();
+            stroke(&mut mesh, &points, true, 2.0, LineCap::Butt, *join);
+
+            let area: f32 = mesh
+                .indices
+                .chunks(3)
+                .map(|triangle| {
+                    let p = |i: u32| mesh.vertices[i as usize].position;
+                    let (a, b, c) = (p(triangle[0]), p(triangle[1]), p(triangle[2]));
+                    ((b.x - a.x) * (c.y - a.y) - (c.x - a.x) * (b.y - a.y)).abs() / 2.0
+                })
+                .sum();
+            // The ring between an 8x8 and a 12x12 square, less the corners
+            // that bevels and rounds cut off.
+            assert!(area <= 80.0 + 1e-3, "{:?} covers {}", join, area);
+            assert!(area >= 78.0, "{:?} covers {}", join, area);
+        }
+    }
+
+    #[test]
+    fn miter_joins_reach_the_corner() {
+        let mut mesh = Mesh::default();
+        let points = line(&[(0.0, 0.0), (10.0, 0.0), (10.0, 10.0)]);
+        stroke(
+            &mut mesh,
+            &points,
+            false,
+            2.0,
+            LineCap::Butt,
+            LineJoin::Miter,
+        );
+
+        // The outer corner of a right angle is at (11, -1).
+        assert!(mesh.vertices.iter().any(|vertex| {
+            (vertex.position.x - 11.0).abs() < 1e-5 && (vertex.position.y + 1.0).abs() < 1e-5
+        }));
+    }
+
+    fn line_list(mesh: &Mesh) -> Vec<((f32, f32), (f32, f32))> {
+        mesh.indices
+            .chunks(2)
+            .map(|pair| {
+                let p = |i: u32| mesh.vertices[i as usize].position;
+                let (a, b) = (p(pair[0]), p(pair[1]));
+                ((a.x, a.y), (b.x, b.y))
+            })
+            .collect()
+    }
+
+    #[test]
+    fn one_pixel_lines_are_dashed_into_line_pairs() {
+        let points = line(&[(0.0, 0.0), (10.0, 0.0), (10.0, 10.0)]);
+        let mut mesh = Mesh::default();
+        dash_line_list(&mut mesh, &points, false, &StrokeStyle::dashed(6.0, 2.0));
+        assert_eq!(
+            line_list(&mesh),
+            vec![
+                ((0.0, 0.0), (6.0, 0.0)),
+                ((8.0, 0.0), (10.0, 0.0)),
+                ((10.0, 0.0), (10.0, 4.0)),
+                ((10.0, 6.0), (10.0, 10.0)),
+            ]
+        );
+
+        // Moving the offset marches the dashes along the line.
+        let mut mesh = Mesh::default();
+        let style = StrokeStyle::dashed(6.0, 2.0).with_dash_offset(-2.0);
+        dash_line_list(&mut mesh, &points, false, &style);
+        assert_eq!(
+            line_list(&mesh)[..2],
+            [((2.0, 0.0), (8.0, 0.0)), ((10.0, 0.0), (10.0, 6.0))]
+        );
+    }
+
+    #[test]
+    fn one_pixel_dots_are_a_pixel_long() {
+        let points = line(&[(0.0, 0.0), (10.0, 0.0)]);
+        let mut mesh = Mesh::default();
+        dash_line_list(&mut mesh, &points, false, &StrokeStyle::dotted(4.0));
+        assert_eq!(
+            line_list(&mesh),
+            vec![
+                ((0.0, 0.0), (1.0, 0.0)),
+                ((4.0, 0.0), (5.0, 0.0)),
+                ((8.0, 0.0), (9.0, 0.0)),
+            ]
+        );
+    }
+
+    #[test]
+    fn solid_one_pixel_lines_are_one_pair_per_segment() {
+        let points = line(&[(0.0, 0.0), (10.0, 0.0), (10.0, 10.0)]);
+        let mut mesh = Mesh::default();
+        dash_line_list(&mut mesh, &points, true, &StrokeStyle::solid());
+        assert_eq!(
+            line_list(&mesh),
+            vec![
+                ((0.0, 0.0), (10.0, 0.0)),
+                ((10.0, 0.0), (10.0, 10.0)),
+                ((10.0, 10.0), (0.0, 0.0)),
+            ]
+        );
+    }
+}
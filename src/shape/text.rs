@@ -1 +1,135 @@
-pub struct Text {}
+use crate::{
+    Color, FontId, FontRegistry, Point2, Renderer, SectionHandle, Shape, TextSection, Vector2,
+};
+pub use wgpu_glyph::{HorizontalAlign, VerticalAlign};
+
+/// A block of text laid out inside normalised bounds.
+///
+/// The text stays on screen until the shape is freed, like the other shapes.
+/// A zero size leaves the text unbounded.
+#[derive(Debug)]
+pub struct Text {
+    position: Vector2,
+    size: Vector2,
+    text: String,
+    color: Color,
+    font: FontId,
+    scale: f32,
+    h_align: HorizontalAlign,
+    v_align: VerticalAlign,
+    wrap: bool,
+    z_index: i32,
+    /// Allocated on the first draw, once the position is known.
+    section_handle: Option<SectionHandle>,
+    dirty: bool,
+}
+
+impl Text {
+    pub fn new(text: &str, size: Vector2, scale: f32) -> Self {
+        Self {
+            position: Vector2::new(0.0, 0.0),
+            size,
+            text: text.to_string(),
+            color: Color::default(),
+            font: FontRegistry::DEFAULT,
+            scale,
+            h_align: HorizontalAlign::Left,
+            v_align: VerticalAlign::Top,
+            wrap: true,
+            z_index: 0,
+            section_handle: None,
+            dirty: true,
+        }
+    }
+
+    /// Release the text so it is no longer drawn.
+    pub fn free(self, renderer: &mut Renderer) {
+        if let Some(handle) = self.section_handle {
+            renderer.text_sections.free(handle);
+        }
+    }
+
+    pub fn set_text(&mut self, text: &str) {
+        if self.text != text {
+            self.text = text.to_string();
+            self.dirty = true;
+        }
+    }
+
+    /// Preferred font. Glyphs it lacks come from the renderer's fallbacks.
+    pub fn set_font(&mut self, font: FontId) {
+        self.font = font;
+        self.dirty = true;
+    }
+
+    /// Font size in pixels.
+    pub fn set_scale(&mut self, scale: f32) {
+        self.scale = scale;
+        self.dirty = true;
+    }
+
+    pub fn set_align(&mut self, h_align: HorizontalAlign, v_align: VerticalAlign) {
+        self.h_align = h_align;
+        self.v_align = v_align;
+        self.dirty = true;
+    }
+
+    /// Wrap words at the bounds, or keep the text on a single line.
+    pub fn set_wrap(&mut self, wrap: bool) {
+        self.wrap = wrap;
+        self.dirty = true;
+    }
+
+    fn section(&self, parent_pos: Point2) -> TextSection {
+        TextSection {
+            text: self.text.clone(),
+            position: parent_pos + self.position,
+            bounds: self.size,
+            color: self.color,
+            font: self.font,
+            scale: self.scale,
+            h_align: self.h_align,
+            v_align: self.v_align,
+            wrap: self.wrap,
+            z_index: self.z_index,
+        }
+    }
+}
+
+impl Shape for Text {
+    fn set_position(&mut self, position: Vector2) {
+        self.position = position;
+        self.dirty = true;
+    }
+
+    fn resize(&mut self, size: Vector2) {
+        self.size = size;
+        self.dirty = true;
+    }
+
+    fn set_color(&mut self, color: Color) {
+        self.color = color;
+        self.dirty = true;
+    }
+
+    fn set_z_index(&mut self, z_index: i32) {
+        self.z_index = z_index;
+        self.dirty = true;
+    }
+
+    fn draw(&mut self, renderer: &mut Renderer, parent_pos: Point2) {
+        if !self.dirty {
+            return;
+        }
+        self.dirty = false;
+
+        let section = self.section(parent_pos);
+        match self
+            .section_handle
+            .and_then(|handle| renderer.text_sections.get_mut(handle))
+        {
+            Some(dst) => *dst = section,
+            None => self.section_handle = Some(renderer.text_sections.alloc(section)),
+        }
+    }
+}
@@ -0,0 +1,468 @@
+//! Ear clipping triangulation of simple polygons with holes.
+
+use crate::Vector2;
+use std::fmt;
+
+/// Errors returned when a polygon can't be triangulated.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PolygonError {
+    /// A ring has fewer than three points or no area.
+    TooFewPoints,
+    /// Two edges cross or touch each other.
+    SelfIntersecting,
+    /// A hole isn't inside the outline, or is inside another hole.
+    HoleOutside,
+    /// A point is NaN or infinite.
+    NotFinite,
+}
+
+impl fmt::Display for PolygonError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PolygonError::TooFewPoints => write!(f, "polygon ring has fewer than three points"),
+            PolygonError::SelfIntersecting => write!(f, "polygon edges intersect"),
+            PolygonError::HoleOutside => write!(f, "polygon hole is outside the outline"),
+            PolygonError::NotFinite => write!(f, "polygon point is not finite"),
+        }
+    }
+}
+
+impl std::error::Error for PolygonError {}
+
+/// Triangulate the polygon with the outline `rings[0]` and the other rings as
+/// holes.
+///
+/// Returns triangle indices into all the rings joined together in order.
+/// Validation and ear clipping both take quadratic time in the number of
+/// points.
+pub(crate) fn triangulate(rings: &[&[Vector2]]) -> Result<Vec<u32>, PolygonError> {
+    validate(rings)?;
+
+    let points: Vec<Vector2> = rings.iter().flat_map(|ring| ring.iter().copied()).collect();
+    let mut first = 0;
+    let mut ring_indices: Vec<Vec<u32>> = rings
+        .iter()
+        .map(|ring| {
+            let indices = (first..(first + ring.len() as u32)).collect();
+            first += ring.len() as u32;
+            indices
+        })
+        .collect();
+
+    // Walk the outline counter-clockwise and the holes clockwise.
+    for (i, (ring, indices)) in rings.iter().zip(&mut ring_indices).enumerate() {
+        if (signed_area(ring) > 0.0) != (i == 0) {
+            indices.reverse();
+        }
+    }
+
+    // Cut each hole into the outline, rightmost first so the cuts don't cross.
+    let mut outline = ring_indices.remove(0);
+    let max_x = |ring: &Vec<u32>| {
+        ring.iter()
+            .map(|&i| points[i as usize].x)
+            .fold(f32::MIN, f32::max)
+    };
+    ring_indices.sort_by(|a, b| max_x(b).total_cmp(&max_x(a)));
+    for hole in &ring_indices {
+        bridge(&points, &mut outline, hole);
+    }
+
+    Ok(ear_clip(&points, outline))
+}
+
+/// Triangulate rings that may nest inside each other, filling with the
+/// even-odd rule: rings inside an even number of others are outlines, and the
+/// rest are holes in the ring directly around them.
+///
+/// Returns triangle indices into all the rings joined together in order.
+pub(crate) fn triangulate_nested(rings: &[&[Vector2]]) -> Result<Vec<u32>, PolygonError> {
+    if rings.iter().any(|ring| ring.len() < 3) {
+        return Err(PolygonError::TooFewPoints);
+    }
+    check_finite(rings)?;
+    check_crossings(rings)?;
+
+    let parents: Vec<Vec<usize>> = rings
+        .iter()
+        .enumerate()
+        .map(|(i, ring)| {
+            (0..rings.len())
+                .filter(|&j| j != i && contains(rings[j], ring[0]))
+                .collect()
+        })
+        .collect();
+    let depth = |i: usize| parents[i].len();
+
+    let mut offsets = Vec::with_capacity(rings.len());
+    let mut first = 0;
+    for ring in rings {
+        offsets.push(first);
+        first += ring.len() as u32;
+    }
+
+    let mut indices = Vec::new();
+    for outline in (0..rings.len()).filter(|&i| depth(i) % 2 == 0) {
+        let holes: Vec<usize> = (0..rings.len())
+            .filter(|&i| depth(i) == depth(outline) + 1 && parents[i].contains(&outline))
+            .collect();
+        let group: Vec<usize> = std::iter::once(outline).chain(holes).collect();
+        let group_rings: Vec<&[Vector2]> = group.iter().map(|&i| rings[i]).collect();
+
+        // Map indices into the group back to indices into all the rings.
+        let mut starts = Vec::with_capacity(group.len());
+        let mut group_first = 0;
+        for &i in &group {
+            starts.push((group_first, offsets[i]));
+            group_first += rings[i].len() as u32;
+        }
+        for index in triangulate(&group_rings)? {
+            let (start, offset) = starts
+                .iter()
+                .rev()
+                .find(|(start, _)| *start <= index)
+                .copied()
+                .unwrap_or_default();
+            indices.push(index - start + offset);
+        }
+    }
+
+    Ok(indices)
+}
+
+fn validate(rings: &[&[Vector2]]) -> Result<(), PolygonError> {
+    if rings.is_empty() {
+        return Err(PolygonError::TooFewPoints);
+    }
+    if rings.iter().any(|ring| ring.len() < 3) {
+        return Err(PolygonError::TooFewPoints);
+    }
+    check_finite(rings)?;
+    check_crossings(rings)?;
+
+    if rings
+        .iter()
+        .any(|ring| signed_area(ring).abs() <= f32::EPSILON)
+    {
+        return Err(PolygonError::TooFewPoints);
+    }
+
+    // With no crossings, one point of each hole tells where the whole hole is.
+    for (i, hole) in rings.iter().enumerate().skip(1) {
+        let point = hole[0];
+        let in_other_hole = rings
+            .iter()
+            .enumerate()
+            .skip(1)
+            .any(|(j, other)| j != i && contains(other, point));
+        if !contains(rings[0], point) || in_other_hole {
+            return Err(PolygonError::HoleOutside);
+        }
+    }
+
+    Ok(())
+}
+
+fn check_finite(rings: &[&[Vector2]]) -> Result<(), PolygonError> {
+    if rings
+        .iter()
+        .flat_map(|ring| ring.iter())
+        .all(|point| point.x.is_finite() && point.y.is_finite())
+    {
+        Ok(())
+    } else {
+        Err(PolygonError::NotFinite)
+    }
+}
+
+fn check_crossings(rings: &[&[Vector2]]) -> Result<(), PolygonError> {
+    // Every pair of edges except neighbours in the same ring.
+    let edges: Vec<(usize, usize, Vector2, Vector2)> = rings
+        .iter()
+        .enumerate()
+        .flat_map(|(r, ring)| {
+            (0..ring.len()).map(move |i| (r, i, ring[i], ring[(i + 1) % ring.len()]))
+        })
+        .collect();
+    for (i, &(ring_a, index_a, a0, a1)) in edges.iter().enumerate() {
+        for &(ring_b, index_b, b0, b1) in &edges[(i + 1)..] {
+            if ring_a == ring_b {
+                let len = rings[ring_a].len();
+                if (index_a + 1) % len == index_b || (index_b + 1) % len == index_a {
+                    continue;
+                }
+            }
+            if segments_intersect(a0, a1, b0, b1) {
+                return Err(PolygonError::SelfIntersecting);
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Twice the area of triangle `a b c`, positive if it turns counter-clockwise.
+fn cross(a: Vector2, b: Vector2, c: Vector2) -> f32 {
+    (b.x - a.x) * (c.y - a.y) - (b.y - a.y) * (c.x - a.x)
+}
+
+fn signed_area(ring: &[Vector2]) -> f32 {
+    (0..ring.len())
+        .map(|i| {
+            let (a, b) = (ring[i], ring[(i + 1) % ring.len()]);
+            a.x * b.y - b.x * a.y
+        })
+        .sum::<f32>()
+        / 2.0
+}
+
+/// Whether `point` is inside `ring`, by counting crossings of a ray to the right.
+fn contains(ring: &[Vector2], point: Vector2) -> bool {
+    let mut inside = false;
+    for i in 0..ring.len() {
+        let (a, b) = (ring[i], ring[(i + 1) % ring.len()]);
+        if (a.y > point.y) != (b.y > point.y) {
+            let x = a.x + (point.y - a.y) * (b.x - a.x) / (b.y - a.y);
+            if point.x < x {
+                inside = !inside;
+            }
+        }
+    }
+    inside
+}
+
+/// Whether `point`, already known to be on the line through `a` and `b`, is
+/// between them.
+fn on_segment(a: Vector2, b: Vector2, point: Vector2) -> bool {
+    point.x >= a.x.min(b.x)
+        && point.x <= a.x.max(b.x)
+        && point.y >= a.y.min(b.y)
+        && point.y <= a.y.max(b.y)
+}
+
+/// Whether segments `a0 a1` and `b0 b1` cross or touch.
+fn segments_intersect(a0: Vector2, a1: Vector2, b0: Vector2, b1: Vector2) -> bool {
+    let d1 = cross(b0, b1, a0);
+    let d2 = cross(b0, b1, a1);
+    let d3 = cross(a0, a1, b0);
+    let d4 = cross(a0, a1, b1);
+    if ((d1 > 0.0 && d2 < 0.0) || (d1 < 0.0 && d2 > 0.0))
+        && ((d3 > 0.0 && d4 < 0.0) || (d3 < 0.0 && d4 > 0.0))
+    {
+        return true;
+    }
+    (d1 == 0.0 && on_segment(b0, b1, a0))
+        || (d2 == 0.0 && on_segment(b0, b1, a1))
+        || (d3 == 0.0 && on_segment(a0, a1, b0))
+        || (d4 == 0.0 && on_segment(a0, a1, b1))
+}
+
+/// Whether `point` is inside or on counter-clockwise triangle `a b c`.
+fn in_triangle(a: Vector2, b: Vector2, c: Vector2, point: Vector2) -> bool {
+    cross(a, b, point) >= 0.0 && cross(b, c, point) >= 0.0 && cross(c, a, point) >= 0.0
+}
+
+/// Join `hole` to `outline` with a pair of edges from the hole's rightmost
+/// point to an outline point it can see, turning both into one ring.
+fn bridge(points: &[Vector2], outline: &mut Vec<u32>, hole: &[u32]) {
+    let (hole_start, hole_point) = hole.iter().map(|&i| points[i as usize]).enumerate().fold(
+        (0, Vector2::new(f32::MIN, 0.0)),
+        |best, (i, point)| {
+            if point.x > best.1.x {
+                (i, point)
+            } else {
+                best
+            }
+        },
+    );
+
+    // Find the closest outline edge hit by a ray from the hole to the right,
+    // and the end of that edge furthest right.
+    let len = outline.len();
+    let mut hit_x = f32::INFINITY;
+    let mut target = 0;
+    for i in 0..len {
+        let (a, b) = (
+            points[outline[i] as usize],
+            points[outline[(i + 1) % len] as usize],
+        );
+        let spans = (a.y <= hole_point.y && hole_point.y <= b.y)
+            || (b.y <= hole_point.y && hole_point.y <= a.y);
+        if spans && a.y != b.y {
+            let x = a.x + (hole_point.y - a.y) * (b.x - a.x) / (b.y - a.y);
+            if x >= hole_point.x && x < hit_x {
+                hit_x = x;
+                target = if a.x > b.x { i } else { (i + 1) % len };
+            }
+        }
+    }
+
+    // Outline points inside the triangle between the ray and that edge end
+    // would block the view. The one closest in angle to the ray can be seen.
+    let hit = Vector2::new(hit_x, hole_point.y);
+    let end = points[outline[target] as usize];
+    let (a, b) = if cross(hole_point, hit, end) >= 0.0 {
+        (hit, end)
+    } else {
+        (end, hit)
+    };
+    let mut best_angle = f32::INFINITY;
+    for (i, &index) in outline.iter().enumerate() {
+        let point = points[index as usize];
+        if point != end && point.x >= hole_point.x && in_triangle(hole_point, a, b, point) {
+            let angle = (point.y - hole_point.y).abs().atan2(point.x - hole_point.x);
+            if angle < best_angle {
+                best_angle = angle;
+                target = i;
+            }
+        }
+    }
+
+    let mut joined = Vec::with_capacity(len + hole.len() + 2);
+    joined.extend_from_slice(&outline[..=target]);
+    for i in 0..=hole.len() {
+        joined.push(hole[(hole_start + i) % hole.len()]);
+    }
+    joined.push(outline[target]);
+    joined.extend_from_slice(&outline[(target + 1)..]);
+    *outline = joined;
+}
+
+/// Whether the corner at `ring[i]` can be cut off as a triangle.
+fn is_ear(points: &[Vector2], ring: &[u32], i: usize) -> bool {
+    let len = ring.len();
+    let a = points[ring[(i + len - 1) % len] as usize];
+    let b = points[ring[i] as usize];
+    let c = points[ring[(i + 1) % len] as usize];
+    if cross(a, b, c) <= 0.0 {
+        return false;
+    }
+    !ring
+        .iter()
+        .map(|&j| points[j as usize])
+        .any(|point| point != a && point != b && point != c && in_triangle(a, b, c, point))
+}
+
+/// Cut ears off a counter-clockwise ring until only a triangle is left.
+fn ear_clip(points: &[Vector2], mut ring: Vec<u32>) -> Vec<u32> {
+    let mut indices = Vec::with_capacity(ring.len().saturating_sub(2) * 3);
+    let mut i = 0;
+    let mut misses = 0;
+    while ring.len() > 3 {
+        let len = ring.len();
+        // Rounding can leave no ear at all, so cut anyway after a full lap.
+        if is_ear(points, &ring, i) || misses >= len {
+            indices.extend_from_slice(&[ring[(i + len - 1) % len], ring[i], ring[(i + 1) % len]]);
+            ring.remove(i);
+            i = if i == 0 { ring.len() - 1 } else { i - 1 };
+            misses = 0;
+        } else {
+            i = (i + 1) % len;
+            misses += 1;
+        }
+    }
+    indices.extend_from_slice(&ring);
+    indices
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ring(points: &[(f32, f32)]) -> Vec<Vector2> {
+        points.iter().map(|&(x, y)| Vector2::new(x, y)).collect()
+    }
+
+    fn square(x: f32, y: f32, size: f32) -> Vec<Vector2> {
+        ring(&[(x, y), (x + size, y), (x + size, y + size), (x, y + size)])
+    }
+
+    /// Total area of the triangles, which is the polygon's area if they
+    /// cover it without overlapping.
+    fn area(rings: &[&[Vector2]], indices: &[u32]) -> f32 {
+        let points: Vec<Vector2> = rings.iter().flat_map(|ring| ring.iter().copied()).collect();
+        indices
+            .chunks(3)
+            .map(|t| {
+                let area = cross(
+                    points[t[0] as usize],
+                    points[t[1] as usize],
+                    points[t[2] as usize],
+                ) / 2.0;
+                assert!(area >= 0.0, "triangle {:?} is clockwise", t);
+                area
+            })
+            .sum()
+    }
+
+    #[test]
+    fn concave_outline() {
+        let c = ring(&[
+            (0.0, 0.0),
+            (3.0, 0.0),
+            (3.0, 1.0),
+            (1.0, 1.0),
+            (1.0, 2.0),
+            (3.0, 2.0),
+            (3.0, 3.0),
+            (0.0, 3.0),
+        ]);
+        let indices = triangulate(&[&c]).unwrap();
+        assert_eq!(indices.len(), 6 * 3);
+        assert!((area(&[&c], &indices) - 7.0).abs() < 1e-4);
+    }
+
+    #[test]
+    fn square_with_one_hole() {
+        let outline = square(0.0, 0.0, 10.0);
+        let hole = square(4.0, 4.0, 2.0);
+        let rings: [&[Vector2]; 2] = [&outline, &hole];
+        let indices = triangulate(&rings).unwrap();
+        // Both rings plus the two points repeated by the bridge.
+        assert_eq!(indices.len(), 8 * 3);
+        assert!((area(&rings, &indices) - 96.0).abs() < 1e-3);
+    }
+
+    #[test]
+    fn square_with_two_holes() {
+        let outline = square(0.0, 0.0, 10.0);
+        let left = square(1.0, 4.0, 2.0);
+        let right = square(6.0, 4.0, 3.0);
+        let rings: [&[Vector2]; 3] = [&outline, &left, &right];
+        let indices = triangulate(&rings).unwrap();
+        assert_eq!(indices.len(), 14 * 3);
+        assert!((area(&rings, &indices) - 87.0).abs() < 1e-3);
+    }
+
+    #[test]
+    fn bow_tie_is_rejected() {
+        let bow_tie = ring(&[(0.0, 0.0), (1.0, 1.0), (1.0, 0.0), (0.0, 1.0)]);
+        assert_eq!(
+            triangulate(&[&bow_tie]),
+            Err(PolygonError::SelfIntersecting)
+        );
+    }
+
+    #[test]
+    fn hole_outside_the_outline_is_rejected() {
+        let outline = square(0.0, 0.0, 10.0);
+        let hole = square(20.0, 0.0, 2.0);
+        assert_eq!(
+            triangulate(&[&outline, &hole]),
+            Err(PolygonError::HoleOutside)
+        );
+    }
+
+    #[test]
+    fn non_finite_points_are_rejected() {
+        let outline = square(0.0, 0.0, 10.0);
+        let hole = ring(&[(4.0, 4.0), (f32::NAN, 4.0), (5.0, 5.0)]);
+        assert_eq!(
+            triangulate(&[&outline, &hole]),
+            Err(PolygonError::NotFinite)
+        );
+        assert_eq!(
+            triangulate_nested(&[&outline, &hole]),
+            Err(PolygonError::NotFinite)
+        );
+    }
+}
@@ -1,11 +1,15 @@
+use md_wgpu::FontRegistry;
 use std::collections::HashMap;
 use stretch::geometry::Size;
 use stretch::number::Number;
 use stretch::style::*;
 
+const INFO_TEXT: &str = "Info";
+
 pub fn stretch(
     width: f32,
     height: f32,
+    fonts: &FontRegistry,
 ) -> Result<
     (
         stretch::node::Stretch,
@@ -17,6 +21,7 @@ pub fn stretch(
 
     let font_size = 24.0;
 
+    let info_fonts = fonts.clone();
     let info = stretch.new_leaf(
         Style {
             size: Size {
@@ -26,11 +31,17 @@ pub fn stretch(
 
             ..Default::default()
         },
-        Box::new(|constraint| {
+        Box::new(move |constraint| {
             println!("info constraint {:?}", constraint);
+            let max_width = match constraint.width {
+                Number::Defined(width) => Some(width),
+                Number::Undefined => None,
+            };
+            let size =
+                info_fonts.measure_text(INFO_TEXT, FontRegistry::DEFAULT, font_size, max_width);
             Ok(Size {
-                width: 43.0,
-                height: 20.0,
+                width: size.width,
+                height: size.height,
             })
         }),
     )?;
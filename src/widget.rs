@@ -63,11 +63,14 @@ impl TargetPinpoint {
     pub fn new(renderer: &mut Renderer, size: Vector2) -> Self {
         let mut border = Polygon::new(&mut *renderer, size, 30);
         border.set_position(size * 0.5);
-        border.set_color(Color::BLACK);
+        border.set_draw_mode(DrawMode::Stroke);
+        border.set_stroke(LineWidth::Pixels(2.0), Color::BLACK);
         let mut target_locator = Polygon::new(&mut *renderer, size / 10.0, 15);
         target_locator.set_position(size * 0.5);
-        target_locator.set_color(Color::GREEN);
+        target_locator.set_color(Color::LIME);
+        target_locator.set_z_index(1);
         let mut crosshair = Lines::new(&mut *renderer, size, 2);
+        crosshair.set_width(Some(LineWidth::Pixels(2.0)));
         crosshair.set_line_position(
             0,
             Point2::new(0.0, size.y / 2.0),